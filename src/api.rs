@@ -1,39 +1,64 @@
-use crate::config::Config;
+use crate::config::{read_certificate, Config, SignOptions};
+use crate::environment::{helper_token, VaultEnvironment};
 use crate::errors::ApiError;
-use reqwest::{header, Client, Method};
-use serde::Deserialize;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
 
+#[derive(Clone)]
 pub struct ApiClient {
     address: String,
+    path: String,
+    namespace: Option<String>,
+    token: String,
     client: Client,
 }
 
 impl ApiClient {
-    /// Create a HTTP client from the config file
-    pub fn from_config(config: &Config) -> Self {
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            "X-Vault-Token",
-            header::HeaderValue::from_str(&config.token)
-                .expect("Failed to convert to header value"),
-        );
+    /// Create a HTTP client from the config file.
+    /// Settings from the Vault CLI environment take precedence over the configuration.
+    pub fn from_config(config: &Config, environment: &VaultEnvironment) -> Self {
+        // Resolve TLS settings
+        let verify = match environment.skip_verify {
+            Some(skip_verify) => !skip_verify,
+            None => config.tls,
+        };
+        let custom_ca = environment
+            .ca_cert
+            .clone()
+            .unwrap_or_else(|| config.custom_ca.clone());
+
+        // Resolve the token, only running the token helper if there is no other and
+        // no authentication method would replace it anyway
+        let token = environment
+            .token
+            .clone()
+            .or_else(|| Some(config.token.clone()).filter(|token| !token.is_empty()))
+            .or_else(|| config.auth.as_ref().map_or_else(helper_token, |_| None))
+            .unwrap_or_default();
 
         // Generate custom client
         let client = Client::builder()
-            .default_headers(headers)
             .use_native_tls()
-            .danger_accept_invalid_certs(config.tls);
+            .danger_accept_invalid_certs(!verify);
 
         ApiClient {
-            address: config.server.clone(),
-            client: if config.custom_ca != "" {
+            address: environment
+                .address
+                .clone()
+                .unwrap_or_else(|| config.server.clone()),
+            path: normalize_path(&config.path),
+            namespace: environment
+                .namespace
+                .clone()
+                .or_else(|| config.namespace.clone()),
+            token,
+            client: if !custom_ca.is_empty() {
                 client
                     .add_root_certificate(
-                        config
-                            .read_certificate()
-                            .expect("Failed to read custom certificate"),
+                        read_certificate(&custom_ca).expect("Failed to read custom certificate"),
                     )
                     .build()
                     .expect("Failed to build API client")
@@ -43,52 +68,220 @@ impl ApiClient {
         }
     }
 
-    /// Ensure the configuration is valid by getting the token permissions
-    pub async fn validate(&self) -> Result<bool, reqwest::Error> {
+    /// Use a different mount path for the SSH secrets engine
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = normalize_path(path);
+        self
+    }
+
+    /// Use a different namespace for all requests
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string()).filter(|namespace| !namespace.is_empty());
+        self
+    }
+
+    /// The mount path of the SSH secrets engine
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Use a different token to authenticate with
+    pub fn set_token(&mut self, token: &str) {
+        self.token = token.to_string();
+    }
+
+    /// The token currently used to authenticate
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Identifies the server and namespace that tokens are issued for
+    pub fn token_key(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}#{}", self.address, namespace),
+            None => self.address.clone(),
+        }
+    }
+
+    /// Ensure the configuration is valid by getting the token permissions.
+    /// Returns the token's details if it is valid.
+    pub async fn validate(&self) -> Result<Option<TokenInfo>, reqwest::Error> {
         let response = self
-            .client
-            .get(&format!("{}/v1/auth/token/lookup-self", self.address).to_string())
+            .request(Method::GET, "auth/token/lookup-self")
             .send()
             .await?;
-        Ok(response.status().is_success())
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+
+        let lookup: LookupResponse = response.json().await?;
+        Ok(Some(lookup.data))
     }
 
-    /// Sign a given public key with the specified role
-    pub async fn sign(&self, role: String, key: String) -> Result<String, ApiError> {
+    /// Renew the token to extend its TTL
+    pub async fn renew(&self) -> Result<Login, ApiError> {
+        let response = self
+            .request(Method::PUT, "auth/token/renew-self")
+            .send()
+            .await?;
+
+        let renewed: LoginResponse = self.handle_response(response).await?;
+        Ok(renewed.auth)
+    }
+
+    /// Log in to an authentication method to obtain a client token.
+    /// The path is relative to `auth/`, i.e. `approle/login`.
+    pub async fn login<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Login, ApiError> {
+        let response = self
+            .unauthenticated(Method::POST, &format!("auth/{}", path))
+            .json(body)
+            .send()
+            .await?;
+
+        let login: LoginResponse = self.handle_response(response).await?;
+        Ok(login.auth)
+    }
+
+    /// Request the URL to begin an OIDC login at the identity provider.
+    /// An empty URL is returned when the redirect URI is not allowed by the role.
+    pub async fn oidc_auth_url(
+        &self,
+        mount: &str,
+        role: Option<&str>,
+        redirect_uri: &str,
+    ) -> Result<String, ApiError> {
         let mut body = HashMap::new();
-        body.insert("public_key", key);
+        body.insert("redirect_uri", redirect_uri);
+        if let Some(role) = role {
+            body.insert("role", role);
+        }
 
         let response = self
-            .client
-            .put(&format!("{}/v1/ssh-ca/sign/{}", self.address, role))
+            .unauthenticated(Method::POST, &format!("auth/{}/oidc/auth_url", mount))
+            .json(&body)
+            .send()
+            .await?;
+
+        let url: AuthUrlResponse = self.handle_response(response).await?;
+        Ok(url.data.auth_url)
+    }
+
+    /// Exchange the parameters received by the OIDC callback for a client token
+    pub async fn oidc_callback(
+        &self,
+        mount: &str,
+        parameters: &[(String, String)],
+    ) -> Result<Login, ApiError> {
+        let response = self
+            .unauthenticated(Method::GET, &format!("auth/{}/oidc/callback", mount))
+            .query(parameters)
+            .send()
+            .await?;
+
+        let login: LoginResponse = self.handle_response(response).await?;
+        Ok(login.auth)
+    }
+
+    /// Ensure an SSH secrets engine is mounted at the configured path
+    pub async fn validate_mount(&self) -> Result<(), ApiError> {
+        let response = self.request(Method::GET, "sys/mounts").send().await?;
+        let mounts: MountsResponse = self.handle_response(response).await?;
+
+        // Mounts are always listed with a trailing slash
+        match mounts.data.get(&format!("{}/", self.path)) {
+            Some(mount) if mount.kind == "ssh" => Ok(()),
+            Some(mount) => Err(ApiError::InvalidMountType(mount.kind.clone())),
+            None => Err(ApiError::MountNotFound(self.path.clone())),
+        }
+    }
+
+    /// Sign a given public key with the specified role
+    pub async fn sign(
+        &self,
+        role: String,
+        key: String,
+        options: &SignOptions,
+    ) -> Result<String, ApiError> {
+        let body = SignRequest {
+            public_key: key,
+            options,
+        };
+
+        let response = self
+            .request(Method::PUT, &format!("{}/sign/{}", self.path, role))
             .json(&body)
             .send()
             .await?;
 
-        // Ensure successful
+        let signed: SignResponse = self.handle_response(response).await?;
+        Ok(signed.data.signed_key)
+    }
+
+    /// Get the public key of the SSH CA in OpenSSH format.
+    /// The endpoint does not require a token.
+    pub async fn public_key(&self) -> Result<String, ApiError> {
+        let response = self
+            .unauthenticated(Method::GET, &format!("{}/public_key", self.path))
+            .send()
+            .await?;
+
         let status = response.status();
-        if status.is_client_error() {
-            let error: ErrorResponse = response.json().await?;
-            Err(self.response_to_error(error))
+        if status.is_success() {
+            return Ok(response.text().await?.trim().to_string());
+        }
+
+        // An unconfigured CA responds with a not found error and no message
+        let error: ErrorResponse = response.json().await.unwrap_or_default();
+        if status == StatusCode::NOT_FOUND && error.errors.is_empty() {
+            Err(ApiError::MissingCaKey(self.path.clone()))
         } else if status.is_server_error() {
             Err(ApiError::ServerError)
         } else {
-            let signed: SignResponse = response.json().await?;
-            Ok(signed.data.signed_key)
+            Err(self.response_to_error(error))
         }
     }
 
     /// Get a list of roles to sign as
     pub async fn list_roles(&self) -> Result<Vec<String>, ApiError> {
         let response = self
-            .client
             .request(
                 Method::from_str("LIST").unwrap(),
-                &format!("{}/v1/ssh-ca/roles", self.address),
+                &format!("{}/roles", self.path),
             )
             .send()
             .await?;
 
+        let roles: RolesResponse = self.handle_response(response).await?;
+        Ok(roles.data.keys)
+    }
+
+    /// Build an authenticated request for a path relative to `/v1/`
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.unauthenticated(method, path)
+            .header("X-Vault-Token", &self.token)
+    }
+
+    /// Build a request without a token for a path relative to `/v1/`
+    fn unauthenticated(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, &format!("{}/v1/{}", self.address, path));
+
+        match &self.namespace {
+            Some(namespace) => request.header("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    /// Ensure a response was successful and decode its body
+    async fn handle_response<T: DeserializeOwned>(
+        &self,
+        response: Response,
+    ) -> Result<T, ApiError> {
         let status = response.status();
         if status.is_client_error() {
             let error: ErrorResponse = response.json().await?;
@@ -96,27 +289,54 @@ impl ApiClient {
         } else if status.is_server_error() {
             Err(ApiError::ServerError)
         } else {
-            let roles: RolesResponse = response.json().await?;
-            Ok(roles.data.keys)
+            Ok(response.json().await?)
         }
     }
 
     /// Convert an error response body to a Rust error
     fn response_to_error(&self, response: ErrorResponse) -> ApiError {
-        if response.errors[0].contains("permission denied") {
+        let message = response
+            .errors
+            .first()
+            .map(String::as_str)
+            .unwrap_or_default();
+        if message.contains("permission denied") {
             ApiError::PermissionDenied
-        } else if response.errors[0].contains("missing public_key")
-            || response.errors[0].contains("failed to parse public_key as SSH key")
+        } else if message.contains("missing public_key")
+            || message.contains("failed to parse public_key as SSH key")
         {
             ApiError::InvalidPublicKey
-        } else if response.errors[0].contains("Unknown role") {
+        } else if message.contains("Unknown role") {
             ApiError::UnknownRole
+        } else if message.contains("no handler for route") {
+            ApiError::MountNotFound(self.path.clone())
+        } else if message.contains("invalid role or secret ID")
+            || message.contains("invalid role ID")
+            || message.contains("invalid secret id")
+            || message.contains("invalid username or password")
+            || message.contains("ldap operation failed")
+        {
+            ApiError::InvalidCredentials
+        } else if !message.is_empty() {
+            ApiError::Rejected(message.to_string())
         } else {
             ApiError::UnknownError
         }
     }
 }
 
+/// Strip any leading or trailing slashes from a mount path
+fn normalize_path(path: &str) -> String {
+    path.trim_matches('/').to_string()
+}
+
+#[derive(Serialize)]
+struct SignRequest<'a> {
+    pub public_key: String,
+    #[serde(flatten)]
+    pub options: &'a SignOptions,
+}
+
 #[derive(Deserialize)]
 struct SignResponse {
     pub data: SignData,
@@ -138,6 +358,55 @@ struct RolesData {
 }
 
 #[derive(Deserialize)]
+struct MountsResponse {
+    pub data: HashMap<String, MountData>,
+}
+
+#[derive(Deserialize)]
+struct MountData {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Deserialize)]
+struct LookupResponse {
+    pub data: TokenInfo,
+}
+
+/// Details about the token in use
+#[derive(Deserialize)]
+pub struct TokenInfo {
+    /// Seconds until the token expires, zero if it never expires
+    pub ttl: u64,
+    pub renewable: bool,
+    pub expire_time: Option<String>,
+    #[serde(default)]
+    pub policies: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct AuthUrlResponse {
+    pub data: AuthUrlData,
+}
+
+#[derive(Deserialize)]
+struct AuthUrlData {
+    pub auth_url: String,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    pub auth: Login,
+}
+
+/// The result of logging in to an authentication method
+#[derive(Deserialize)]
+pub struct Login {
+    pub client_token: String,
+    pub lease_duration: u64,
+}
+
+#[derive(Default, Deserialize)]
 struct ErrorResponse {
     pub errors: Vec<String>,
 }
@@ -0,0 +1,615 @@
+use crate::api::{ApiClient, Login, TokenInfo};
+use crate::config::Auth;
+use crate::errors::AuthError;
+use crate::util::{cache_file, format_duration, now};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{read_to_string, OpenOptions};
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::os::unix::fs::OpenOptionsExt;
+use std::thread;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use url::Url;
+
+/// Name of the token cache file within the cache directory
+const TOKEN_CACHE: &str = "tokens.json";
+
+/// Cached tokens are treated as expired this many seconds before they actually expire
+const EXPIRY_MARGIN: u64 = 30;
+
+/// Tokens expiring within this many seconds are renewed when no threshold is configured
+pub const DEFAULT_RENEW_THRESHOLD: u64 = 900;
+
+/// Port the OIDC callback listener uses when none is configured
+pub const DEFAULT_CALLBACK_PORT: u16 = 8250;
+
+/// Seconds to wait for the identity provider to redirect back during an OIDC login
+const CALLBACK_TIMEOUT: u64 = 300;
+
+/// Maximum size of the request head accepted by the OIDC callback listener
+const MAX_CALLBACK_SIZE: usize = 16 * 1024;
+
+/// Tokens obtained from logging in, keyed by the address of the Vault server, namespace
+/// and the identity logged in as. Tokens from logins without a configured method are
+/// only keyed by the server and namespace.
+#[derive(Default, Serialize, Deserialize)]
+pub struct TokenCache {
+    tokens: HashMap<String, CachedToken>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedToken {
+    token: String,
+    expires_at: Option<u64>,
+    /// Method to log in again with once a token used without a configured method expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth: Option<Auth>,
+}
+
+impl TokenCache {
+    /// Read the token cache, starting with an empty one if it does not exist
+    pub fn read() -> Result<Self, AuthError> {
+        let path = cache_file(TOKEN_CACHE)?;
+        if !path.exists() {
+            return Ok(TokenCache::default());
+        }
+
+        let raw = read_to_string(path)?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+
+    /// Write the token cache so only the current user can read it
+    pub fn write(&self) -> Result<(), AuthError> {
+        let encoded = serde_json::to_string(self)?;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .mode(0o600)
+            .open(cache_file(TOKEN_CACHE)?)?;
+
+        file.write_all(encoded.as_bytes())?;
+        Ok(())
+    }
+
+    /// Get the cached token for a key if it has not expired
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let cached = self.tokens.get(key)?;
+        match cached.expires_at {
+            Some(expires_at) if expires_at <= now() + EXPIRY_MARGIN => None,
+            _ => Some(&cached.token),
+        }
+    }
+
+    /// Update the expiry of every cached copy of a token after it was renewed
+    pub fn renewed(&mut self, login: &Login) {
+        let keys = self
+            .tokens
+            .iter()
+            .filter(|(_, cached)| cached.token == login.client_token)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in keys {
+            let auth = self.tokens.remove(&key).and_then(|cached| cached.auth);
+            self.insert(&key, login, auth);
+        }
+    }
+
+    /// Store the token from a login under a key, along with the method to log in again with
+    pub fn insert(&mut self, key: &str, login: &Login, auth: Option<Auth>) {
+        // A lease duration of zero means the token never expires
+        let expires_at = if login.lease_duration == 0 {
+            None
+        } else {
+            Some(now() + login.lease_duration)
+        };
+
+        self.tokens.insert(
+            key.to_string(),
+            CachedToken {
+                token: login.client_token.clone(),
+                expires_at,
+                auth,
+            },
+        );
+    }
+}
+
+/// Authenticate the client using the configured method.
+/// A cached token is reused if it is still valid, otherwise a new one is obtained.
+/// Without a configured method, the static token is used unless a cached one is valid.
+/// If there is no static token, an expired login from `vssh login` is repeated with the
+/// method it used.
+pub async fn authenticate(client: &mut ApiClient, auth: Option<&Auth>) -> Result<(), AuthError> {
+    let cache = TokenCache::read()?;
+
+    // Attempt to use the cached token
+    if let Some(token) = cache.get(&cache_key(client, auth)) {
+        let fallback = client.token().to_string();
+        client.set_token(token);
+        if client.validate().await?.is_some() {
+            return Ok(());
+        }
+
+        client.set_token(&fallback);
+    }
+
+    // Obtain a new token
+    match auth {
+        Some(auth @ Auth::AppRole { .. }) => login(client, auth, false).await,
+        Some(auth) => {
+            leg::warn("Not logged in or the session expired", None, None);
+            login(client, auth, false).await
+        }
+        None if !client.token().is_empty() => Ok(()),
+        None => match cache.tokens.get(&cache_key(client, None)) {
+            Some(CachedToken {
+                auth: Some(auth), ..
+            }) => {
+                leg::warn("The session expired", None, None);
+                login(client, auth, true).await
+            }
+            Some(_) => Err(AuthError::SessionExpired),
+            None => Ok(()),
+        },
+    }
+}
+
+/// Log in with the given method and cache the obtained token.
+/// Methods requiring a password will prompt for it.
+/// The token is also used by default when no method is configured if `default` is set.
+pub async fn login(client: &mut ApiClient, auth: &Auth, default: bool) -> Result<(), AuthError> {
+    let login = match auth {
+        Auth::AppRole {
+            mount,
+            role_id,
+            secret_id_file,
+            secret_id_env,
+        } => approle(client, mount, role_id, secret_id_file, secret_id_env).await?,
+        Auth::Userpass { mount, username } | Auth::Ldap { mount, username } => {
+            password(client, mount, username).await?
+        }
+        Auth::Oidc { mount, role, port } => {
+            // Opening the browser may block, so it is done separately
+            oidc(client, mount, role, *port, |url| {
+                thread::spawn(move || webbrowser::open(&url));
+            })
+            .await?
+        }
+    };
+    client.set_token(&login.client_token);
+
+    // Save for future invocations
+    let mut cache = TokenCache::read()?;
+    cache.insert(&cache_key(client, Some(auth)), &login, None);
+    if default {
+        cache.insert(&cache_key(client, None), &login, Some(auth.clone()));
+    }
+    cache.write()?;
+
+    Ok(())
+}
+
+/// Renew the token if it expires within the threshold.
+/// A warning is shown if it will still expire soon afterwards.
+pub async fn renew(client: &ApiClient, info: &TokenInfo, threshold: u64) -> Result<(), AuthError> {
+    // Tokens with a TTL of zero never expire
+    if info.ttl == 0 || info.ttl > threshold {
+        return Ok(());
+    }
+
+    // Extend the lifetime if possible
+    let ttl = if info.renewable {
+        let renewed = client.renew().await?;
+
+        // Keep the cached expiry in sync
+        let mut cache = TokenCache::read()?;
+        cache.renewed(&renewed);
+        cache.write()?;
+
+        leg::info(
+            &format!(
+                "Renewed token, now valid for {}",
+                format_duration(renewed.lease_duration)
+            ),
+            None,
+            None,
+        );
+        renewed.lease_duration
+    } else {
+        info.ttl
+    };
+
+    // The token may have reached its maximum TTL
+    if ttl <= threshold {
+        let at = match &info.expire_time {
+            Some(expire_time) if !info.renewable => format!(" at {}", expire_time),
+            _ => String::new(),
+        };
+        leg::warn(
+            &format!(
+                "Token expires in {}{}, log in again or request a new token soon",
+                format_duration(ttl),
+                at
+            ),
+            None,
+            None,
+        );
+    }
+
+    Ok(())
+}
+
+/// Key of the cached token for the client's server and namespace, and the identity of the
+/// method if one is configured
+fn cache_key(client: &ApiClient, auth: Option<&Auth>) -> String {
+    match auth {
+        Some(auth) => format!("{}#{}", client.token_key(), auth.identity()),
+        None => client.token_key(),
+    }
+}
+
+/// Log in using an AppRole, reading the secret ID from a file or environment variable
+async fn approle(
+    client: &ApiClient,
+    mount: &str,
+    role_id: &str,
+    secret_id_file: &Option<String>,
+    secret_id_env: &Option<String>,
+) -> Result<Login, AuthError> {
+    let secret_id = if let Some(file) = secret_id_file {
+        read_to_string(file)?.trim().to_string()
+    } else if let Some(variable) = secret_id_env {
+        env::var(variable).map_err(|_| AuthError::MissingEnvironmentVariable(variable.clone()))?
+    } else {
+        return Err(AuthError::MissingSecretId);
+    };
+
+    let mut body = HashMap::new();
+    body.insert("role_id", role_id);
+    body.insert("secret_id", &secret_id);
+
+    Ok(client.login(&format!("{}/login", mount), &body).await?)
+}
+
+/// Log in using a username and password, prompting for the password without echoing it.
+/// Both the userpass and LDAP methods use this flow.
+async fn password(client: &ApiClient, mount: &str, username: &str) -> Result<Login, AuthError> {
+    let password =
+        rpassword::read_password_from_tty(Some(&format!("Password for '{}': ", username)))?;
+
+    let mut body = HashMap::new();
+    body.insert("password", password);
+
+    Ok(client
+        .login(&format!("{}/login/{}", mount, username), &body)
+        .await?)
+}
+
+/// Log in through the browser using OIDC, which `open_browser` is given the login page for.
+/// The identity provider redirects back to a listener on the loopback interface,
+/// and the parameters it receives are exchanged with Vault for a token.
+async fn oidc(
+    client: &ApiClient,
+    mount: &str,
+    role: &Option<String>,
+    port: u16,
+    open_browser: impl FnOnce(String),
+) -> Result<Login, AuthError> {
+    // Listen before requesting the URL so the callback cannot be missed
+    let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+    let redirect_uri = format!("http://localhost:{}/oidc/callback", port);
+
+    // Get the login page from the identity provider
+    let url = client
+        .oidc_auth_url(mount, role.as_deref(), &redirect_uri)
+        .await?;
+    if url.is_empty() {
+        return Err(AuthError::RedirectNotAllowed(redirect_uri));
+    }
+
+    leg::info(
+        &format!("Complete the login in your browser: {}", url),
+        None,
+        None,
+    );
+    open_browser(url);
+
+    // Wait for the identity provider to redirect back
+    let (mut stream, parameters) = match timeout(
+        Duration::from_secs(CALLBACK_TIMEOUT),
+        wait_for_callback(&mut listener),
+    )
+    .await
+    {
+        Ok(callback) => callback?,
+        Err(_) => return Err(AuthError::CallbackTimeout),
+    };
+
+    // Errors from the identity provider are passed as parameters
+    let error = parameters
+        .iter()
+        .find(|(key, _)| key == "error_description")
+        .or_else(|| parameters.iter().find(|(key, _)| key == "error"));
+    if let Some((_, error)) = error {
+        respond(
+            &mut stream,
+            "400 Bad Request",
+            "Login failed, check the terminal for details",
+        )
+        .await?;
+        return Err(AuthError::ProviderError(error.clone()));
+    }
+
+    // Exchange for a token and let the user know the result
+    match client.oidc_callback(mount, &parameters).await {
+        Ok(login) => {
+            respond(
+                &mut stream,
+                "200 OK",
+                "Login successful, you may close this window",
+            )
+            .await?;
+            Ok(login)
+        }
+        Err(e) => {
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "Login failed, check the terminal for details",
+            )
+            .await?;
+            Err(e.into())
+        }
+    }
+}
+
+/// Accept connections until one requests the OIDC callback path.
+/// Returns the connection along with the query parameters of the request.
+async fn wait_for_callback(
+    listener: &mut TcpListener,
+) -> Result<(TcpStream, Vec<(String, String)>), AuthError> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+
+        // Read the request head
+        let mut head = Vec::new();
+        let mut buffer = [0; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_CALLBACK_SIZE
+        {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&buffer[..read]);
+        }
+
+        // Extract the target from the request line
+        let head = String::from_utf8_lossy(&head);
+        let target = head
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or_default();
+        let url = match Url::parse(&format!("http://localhost{}", target)) {
+            Ok(url) if url.path() == "/oidc/callback" => url,
+            _ => {
+                respond(&mut stream, "404 Not Found", "Not found").await?;
+                continue;
+            }
+        };
+
+        let parameters = url.query_pairs().into_owned().collect();
+        return Ok((stream, parameters));
+    }
+}
+
+/// Send a minimal HTML response to the browser
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) -> io::Result<()> {
+    let body = format!("<html><body><p>{}</p></body></html>", message);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::environment::VaultEnvironment;
+    use std::io::Read;
+    use std::net::{Shutdown, TcpListener as StdListener, TcpStream as StdStream};
+    use std::sync::mpsc;
+    use std::thread::JoinHandle;
+
+    /// Serve the responses to one request each from a stub Vault server,
+    /// returning its address and the requests it received once they are all answered
+    fn stub_vault(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = StdListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|body| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let request = read_request(&mut stream);
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                    request
+                })
+                .collect()
+        });
+        (address, handle)
+    }
+
+    /// Read a request head and its body
+    fn read_request(stream: &mut StdStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        let head_end = loop {
+            let read = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                break end + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+        let length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map_or(0, |length| length.trim().parse().unwrap());
+        while request.len() < head_end + length {
+            let read = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..read]);
+        }
+        String::from_utf8(request).unwrap()
+    }
+
+    /// Act as the browser being redirected back, first requesting an unrelated path,
+    /// and send the response to the callback
+    fn redirect_back(port: u16, query: &'static str) -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for target in &["/favicon.ico", query] {
+                let mut stream = StdStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+                write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+                stream.shutdown(Shutdown::Write).unwrap();
+
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                if target.starts_with("/oidc/callback") {
+                    sender.send(response).unwrap();
+                }
+            }
+        });
+        receiver
+    }
+
+    /// A port nothing is listening on
+    fn free_port() -> u16 {
+        let listener = StdListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    fn client(address: &str) -> ApiClient {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "server": address,
+            "token": "",
+            "path": "ssh-ca",
+            "custom_ca": "",
+            "tls": true,
+            "namespace": null,
+            "auth": null,
+            "renew_threshold": null,
+            "cache_margin": null,
+            "profiles": {},
+        }))
+        .unwrap();
+        ApiClient::from_config(&config, &VaultEnvironment::default())
+    }
+
+    #[tokio::test]
+    async fn oidc_exchanges_callback_for_token() {
+        let (address, vault) = stub_vault(vec![
+            String::from(r#"{"data":{"auth_url":"https://idp.example/authorize?state=s1"}}"#),
+            String::from(r#"{"auth":{"client_token":"s.oidc","lease_duration":3600}}"#),
+        ]);
+        let port = free_port();
+
+        let mut opened = None;
+        let mut browser = None;
+        let login = oidc(
+            &client(&address),
+            "oidc",
+            &Some(String::from("dev")),
+            port,
+            |url| {
+                opened = Some(url);
+                browser = Some(redirect_back(port, "/oidc/callback?state=s1&code=c%201"));
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(login.client_token, "s.oidc");
+        assert_eq!(login.lease_duration, 3600);
+        assert_eq!(
+            opened.as_deref(),
+            Some("https://idp.example/authorize?state=s1")
+        );
+        assert!(browser
+            .unwrap()
+            .recv()
+            .unwrap()
+            .starts_with("HTTP/1.1 200 OK"));
+
+        let requests = vault.join().unwrap();
+        assert!(requests[0].starts_with("POST /v1/auth/oidc/oidc/auth_url "));
+        let body: serde_json::Value =
+            serde_json::from_str(requests[0].split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(
+            body["redirect_uri"],
+            format!("http://localhost:{}/oidc/callback", port)
+        );
+        assert_eq!(body["role"], "dev");
+        assert!(requests[1].starts_with("GET /v1/auth/oidc/oidc/callback?state=s1&code=c+1 "));
+    }
+
+    #[tokio::test]
+    async fn oidc_reports_provider_errors() {
+        let (address, vault) = stub_vault(vec![String::from(
+            r#"{"data":{"auth_url":"https://idp.example/authorize"}}"#,
+        )]);
+        let port = free_port();
+
+        let mut browser = None;
+        let result = oidc(&client(&address), "oidc", &None, port, |_| {
+            browser = Some(redirect_back(
+                port,
+                "/oidc/callback?error=access_denied&error_description=Denied%20by%20user",
+            ));
+        })
+        .await;
+
+        match result {
+            Err(AuthError::ProviderError(error)) => assert_eq!(error, "Denied by user"),
+            _ => panic!("expected the provider's error"),
+        }
+        assert!(browser
+            .unwrap()
+            .recv()
+            .unwrap()
+            .starts_with("HTTP/1.1 400 Bad Request"));
+        assert_eq!(vault.join().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn oidc_rejects_empty_auth_url() {
+        let (address, _) = stub_vault(vec![String::from(r#"{"data":{"auth_url":""}}"#)]);
+        let port = free_port();
+
+        match oidc(&client(&address), "oidc", &None, port, |_| {}).await {
+            Err(AuthError::RedirectNotAllowed(uri)) => {
+                assert_eq!(uri, format!("http://localhost:{}/oidc/callback", port))
+            }
+            _ => panic!("expected the redirect URI to be rejected"),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::api::ApiClient;
+use crate::config::SignOptions;
+use crate::errors::{CacheError, SshError};
+use crate::ssh::fingerprint;
+use crate::util::{cache_file, now};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{read_to_string, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+
+/// Name of the certificate cache file within the cache directory
+const CERTIFICATE_CACHE: &str = "certificates.json";
+
+/// Cached certificates are not reused within this many seconds of expiring unless configured
+pub const DEFAULT_CACHE_MARGIN: u64 = 60;
+
+/// Signed certificates that can be reused for the same request
+#[derive(Default, Serialize, Deserialize)]
+pub struct CertificateCache {
+    certificates: HashMap<String, CachedCertificate>,
+}
+
+/// What a certificate was requested for, used to identify it in the cache.
+/// Every signing parameter is included so that a certificate is only reused for the same ones.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CertificateRequest {
+    pub server: String,
+    pub mount: String,
+    pub role: String,
+    pub fingerprint: String,
+    pub principals: Option<String>,
+    #[serde(default)]
+    pub ttl: Option<String>,
+    #[serde(default)]
+    pub cert_type: Option<String>,
+    #[serde(default)]
+    pub key_id: Option<String>,
+    #[serde(default)]
+    pub critical_options: BTreeMap<String, String>,
+    #[serde(default)]
+    pub extensions: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CachedCertificate {
+    #[serde(flatten)]
+    pub request: CertificateRequest,
+    pub certificate: String,
+    pub valid_before: u64,
+}
+
+impl CertificateRequest {
+    /// Describe signing a public key with a role on the client's server
+    pub fn new(
+        client: &ApiClient,
+        role: &str,
+        public_key: &str,
+        options: &SignOptions,
+    ) -> Result<Self, SshError> {
+        Ok(CertificateRequest {
+            server: client.token_key(),
+            mount: client.path().to_string(),
+            role: role.to_string(),
+            fingerprint: fingerprint(public_key)?,
+            principals: options.valid_principals.clone(),
+            ttl: options.ttl.clone(),
+            cert_type: options.cert_type.clone(),
+            key_id: options.key_id.clone(),
+            critical_options: options.critical_options.clone().into_iter().collect(),
+            extensions: options.extensions.clone().into_iter().collect(),
+        })
+    }
+
+    /// A digest of every field so that none can collide with another
+    fn key(&self) -> String {
+        let mut fields = vec![
+            self.server.as_str(),
+            &self.mount,
+            &self.role,
+            &self.fingerprint,
+            self.principals.as_deref().unwrap_or_default(),
+            self.ttl.as_deref().unwrap_or_default(),
+            self.cert_type.as_deref().unwrap_or_default(),
+            self.key_id.as_deref().unwrap_or_default(),
+        ];
+        for map in &[&self.critical_options, &self.extensions] {
+            for (name, value) in map.iter() {
+                fields.push(name);
+                fields.push(value);
+            }
+        }
+
+        // The number of entries keeps them from shifting between the maps
+        let mut hasher = Sha256::new();
+        hasher.update((self.critical_options.len() as u64).to_be_bytes());
+        hasher.update((self.extensions.len() as u64).to_be_bytes());
+        for field in fields {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+impl CachedCertificate {
+    /// Whether the certificate is still valid for at least the margin
+    pub fn is_valid(&self, margin: u64) -> bool {
+        self.valid_before > now().saturating_add(margin)
+    }
+}
+
+impl CertificateCache {
+    /// Read the certificate cache, starting with an empty one if it does not exist
+    pub fn read() -> Result<Self, CacheError> {
+        let path = cache_file(CERTIFICATE_CACHE)?;
+        if !path.exists() {
+            return Ok(CertificateCache::default());
+        }
+
+        let raw = read_to_string(path)?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+
+    /// Write the certificate cache so only the current user can read it
+    pub fn write(&self) -> Result<(), CacheError> {
+        let encoded = serde_json::to_string(self)?;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .mode(0o600)
+            .open(cache_file(CERTIFICATE_CACHE)?)?;
+
+        file.write_all(encoded.as_bytes())?;
+        Ok(())
+    }
+
+    /// Get a cached certificate for a request if it is valid for at least the margin
+    pub fn get(&self, request: &CertificateRequest, margin: u64) -> Option<&CachedCertificate> {
+        self.certificates
+            .get(&request.key())
+            .filter(|cached| cached.is_valid(margin))
+    }
+
+    /// Store the certificate signed for a request, replacing any previous one
+    pub fn insert(&mut self, request: CertificateRequest, certificate: String, valid_before: u64) {
+        self.certificates.insert(
+            request.key(),
+            CachedCertificate {
+                request,
+                certificate,
+                valid_before,
+            },
+        );
+    }
+
+    /// All cached certificates ordered by expiry
+    pub fn entries(&self) -> Vec<&CachedCertificate> {
+        let mut entries = self.certificates.values().collect::<Vec<_>>();
+        entries.sort_by_key(|cached| cached.valid_before);
+        entries
+    }
+
+    /// Remove certificates that are no longer valid, returning how many were removed
+    pub fn remove_expired(&mut self) -> usize {
+        let before = self.certificates.len();
+        self.certificates.retain(|_, cached| cached.is_valid(0));
+        before - self.certificates.len()
+    }
+
+    /// Remove every certificate, returning how many were removed
+    pub fn clear(&mut self) -> usize {
+        let count = self.certificates.len();
+        self.certificates.clear();
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh::FOREVER;
+
+    fn request() -> CertificateRequest {
+        CertificateRequest {
+            server: String::from("https://vault.example.com:8200"),
+            mount: String::from("ssh-ca"),
+            role: String::from("dev"),
+            fingerprint: String::from("SHA256:abc"),
+            principals: None,
+            ttl: None,
+            cert_type: None,
+            key_id: None,
+            critical_options: BTreeMap::new(),
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn every_sign_option_changes_key() {
+        let base = request().key();
+        let mut changed = Vec::new();
+
+        let mut with = request();
+        with.principals = Some(String::from("alice"));
+        changed.push(with);
+        let mut with = request();
+        with.ttl = Some(String::from("5m"));
+        changed.push(with);
+        let mut with = request();
+        with.cert_type = Some(String::from("host"));
+        changed.push(with);
+        let mut with = request();
+        with.key_id = Some(String::from("deploy"));
+        changed.push(with);
+        let mut with = request();
+        with.critical_options
+            .insert(String::from("force-command"), String::from("uptime"));
+        changed.push(with);
+        let mut with = request();
+        with.extensions
+            .insert(String::from("permit-pty"), String::new());
+        changed.push(with);
+
+        for request in &changed {
+            assert_ne!(request.key(), base);
+        }
+    }
+
+    #[test]
+    fn options_do_not_shift_between_maps() {
+        let mut critical = request();
+        critical
+            .critical_options
+            .insert(String::from("permit-pty"), String::new());
+        let mut extension = request();
+        extension
+            .extensions
+            .insert(String::from("permit-pty"), String::new());
+
+        assert_ne!(critical.key(), extension.key());
+    }
+
+    #[test]
+    fn fields_do_not_shift_into_each_other() {
+        let mut first = request();
+        first.role = String::from("dev");
+        first.fingerprint = String::from("SHA256:abc");
+        let mut second = request();
+        second.role = String::from("devSHA256:abc");
+        second.fingerprint = String::new();
+
+        assert_ne!(first.key(), second.key());
+    }
+
+    #[test]
+    fn reused_only_for_same_options() {
+        let mut signed = request();
+        signed.ttl = Some(String::from("1h"));
+        let mut cache = CertificateCache::default();
+        cache.insert(signed.clone(), String::from("certificate"), FOREVER);
+
+        assert!(cache.get(&signed, 0).is_some());
+        assert!(cache.get(&request(), 0).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::{DynamicForward, Forward, SignOptions};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -11,11 +12,18 @@ pub struct Opts {
     /// Sets a custom config file
     #[structopt(short, long, env)]
     pub config: Option<String>,
+    /// Overrides the path of the SSH CA on the Vault server
+    #[structopt(long)]
+    pub path: Option<String>,
+    /// Overrides the Vault Enterprise namespace to use
+    #[structopt(long)]
+    pub namespace: Option<String>,
     #[structopt(subcommand)]
     pub cmd: Command,
 }
 
 #[derive(Debug, StructOpt)]
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
     /// Repair a user's configuration file
     RepairConfig,
@@ -39,6 +47,59 @@ pub enum Command {
         #[structopt(long)]
         /// Path to the public part of the custom certificate authority
         custom_ca: Option<String>,
+        #[structopt(long)]
+        /// Vault Enterprise namespace to use
+        namespace: Option<String>,
+        #[structopt(
+            long,
+            possible_values = &["token", "approle", "userpass", "ldap", "oidc"]
+        )]
+        /// Method to authenticate with, defaults to a static token
+        auth_method: Option<String>,
+        #[structopt(long)]
+        /// Path the authentication method is mounted at
+        auth_mount: Option<String>,
+        #[structopt(long)]
+        /// Role ID to log in with when using AppRole
+        role_id: Option<String>,
+        #[structopt(long)]
+        /// File to read the AppRole secret ID from
+        secret_id_file: Option<String>,
+        #[structopt(long)]
+        /// Environment variable to read the AppRole secret ID from
+        secret_id_env: Option<String>,
+        #[structopt(long)]
+        /// Username to log in as when using userpass or LDAP, defaults to the current user
+        username: Option<String>,
+        #[structopt(long)]
+        /// Role to log in with when using OIDC
+        oidc_role: Option<String>,
+        #[structopt(long)]
+        /// Port to listen on for the OIDC callback
+        callback_port: Option<u16>,
+        #[structopt(long)]
+        /// Use the Vault CLI's environment variables and token for any values not provided
+        import_vault_env: bool,
+    },
+    /// Log in to the server with a username and password or through OIDC
+    ///
+    /// The obtained token is cached and used until it expires
+    Login {
+        #[structopt(short, long, possible_values = &["userpass", "ldap", "oidc"])]
+        /// Authentication method to use, defaults to the configured method
+        method: Option<String>,
+        #[structopt(long)]
+        /// Path the authentication method is mounted at
+        mount: Option<String>,
+        #[structopt(short, long)]
+        /// Username to log in as, defaults to the configured username or current user
+        username: Option<String>,
+        #[structopt(short, long)]
+        /// OIDC role to log in with, defaults to the role configured on the server
+        role: Option<String>,
+        #[structopt(long)]
+        /// Port to listen on for the OIDC callback
+        port: Option<u16>,
     },
     #[structopt(alias = "ls")]
     /// List available roles
@@ -52,6 +113,8 @@ pub enum Command {
         /// File to write the signed certificate to
         #[structopt(short, long)]
         output: Option<String>,
+        #[structopt(flatten)]
+        signing: SignArgs,
     },
     /// Connect to a server with an automatically generated signed certificate
     Connect {
@@ -68,12 +131,163 @@ pub enum Command {
         #[structopt(short = "f", long)]
         /// Connect for SFTP instead of pure SSH
         sftp: bool,
-        #[structopt(short, long)]
-        /// Extra SSH client options
+        #[structopt(short, long, allow_hyphen_values = true)]
+        /// Extra SSH client options, quoted like in a shell
         options: Option<String>,
+        #[structopt(short, long, conflicts_with_all = &["private-key", "public-key"])]
+        /// Generate a key pair for this session that is removed afterwards
+        ephemeral: bool,
+        #[structopt(short, long)]
+        /// Load the key and certificate into the running ssh-agent instead of passing them to ssh
+        agent: bool,
+        #[structopt(flatten)]
+        signing: SignArgs,
+        #[structopt(last = true)]
+        /// Command to run on the server instead of a shell, given after --
+        remote_command: Vec<String>,
+    },
+    /// Copy files with scp using a signed certificate
+    Scp(Transfer),
+    /// Copy files with rsync over ssh using a signed certificate
+    Rsync(Transfer),
+    /// Sign a key and add it to the running ssh-agent
+    ///
+    /// The agent removes the key when the certificate expires
+    AgentAdd {
+        /// Role to sign public key with
+        role: String,
+        #[structopt(short, long)]
+        /// Private key to add, defaults to ~/.ssh/id_rsa
+        private_key: Option<String>,
+        #[structopt(short = "k", long)]
+        /// Alternative public key to sign
+        public_key: Option<String>,
+        #[structopt(short, long, conflicts_with_all = &["private-key", "public-key"])]
+        /// Generate a key pair that only exists in the agent
+        ephemeral: bool,
+        #[structopt(flatten)]
+        signing: SignArgs,
+    },
+    /// Run an SSH agent that signs the key with Vault whenever a client needs it
+    ///
+    /// Set SSH_AUTH_SOCK to the printed path to use it from any ssh based tool
+    Agent {
+        /// Role to sign public key with
+        role: String,
+        #[structopt(short, long)]
+        /// Private key to sign with, defaults to ~/.ssh/id_rsa
+        private_key: Option<String>,
+        #[structopt(short = "k", long)]
+        /// Alternative public key to sign
+        public_key: Option<String>,
+        #[structopt(short, long, conflicts_with_all = &["private-key", "public-key"])]
+        /// Generate a key pair that only exists in memory
+        ephemeral: bool,
+        #[structopt(short, long)]
+        /// Path of the socket to listen at, defaults to ~/.cache/vssh/agent.sock
+        socket: Option<String>,
+        #[structopt(flatten)]
+        signing: SignArgs,
+    },
+    /// Ensure a certificate exists for a host, then relay stdin and stdout to it
+    ///
+    /// Use as `ProxyCommand vssh proxy %h %p` so plain ssh, scp and rsync get certificates.
+    /// The certificate is written next to the private key as `*-cert.pub`
+    Proxy {
+        /// Host to connect to
+        host: String,
+        #[structopt(default_value = "22")]
+        /// Port to connect to
+        port: u16,
+        #[structopt(short, long, conflicts_with = "profile")]
+        /// Role to sign public key with, defaults to that of the profile for the host
+        role: Option<String>,
+        #[structopt(long)]
+        /// Profile to use instead of the one whose address is the host
+        profile: Option<String>,
+        #[structopt(short, long)]
+        /// Private key to authenticate with
+        private_key: Option<String>,
+        #[structopt(short = "k", long)]
+        /// Alternative public key to use
+        public_key: Option<String>,
+        #[structopt(long)]
+        /// Only ensure a certificate exists without connecting, for use with `Match exec`
+        check: bool,
+        #[structopt(flatten)]
+        signing: SignArgs,
+    },
+    /// Run a command on every profile matching a pattern
+    ///
+    /// Every line of output is prefixed with the name of the profile it came from
+    Exec {
+        #[structopt(long)]
+        /// Comma separated patterns of profile names, i.e. `web-*`
+        profiles: String,
+        #[structopt(long, default_value = "10")]
+        /// Maximum number of commands to run at once
+        parallel: usize,
+        #[structopt(long)]
+        /// Stop once the command fails on a profile, terminating those still running
+        fail_fast: bool,
+        #[structopt(last = true, required = true)]
+        /// Command to run on the servers, given after --
+        remote_command: Vec<String>,
+    },
+    /// Keep the port forwards and SOCKS proxy of a profile open
+    ///
+    /// Reconnects when the connection drops, signing new certificates if the old ones are about to expire
+    Tunnel {
+        /// Name of the profile
+        name: String,
+        #[structopt(long, default_value = "5")]
+        /// Seconds to wait before reconnecting, doubling while the connection keeps dropping
+        retry_delay: u64,
     },
     /// Manage server profiles
     Profiles(Profiles),
+    /// Manage the certificates of the current host
+    Host(Host),
+    /// Work with the SSH certificate authority
+    Ca(Ca),
+    /// Configure the current host's SSH server
+    Server(Server),
+    /// Manage the cache of signed certificates
+    Cache(Cache),
+    /// Work with signed certificates
+    Cert(Cert),
+    /// Manage the ssh client configuration for profiles
+    SshConfig(SshConfig),
+}
+
+/// Options for copying files to or from servers
+#[derive(Debug, StructOpt)]
+pub struct Transfer {
+    /// Role to sign public key with, or a profile to use the settings of
+    pub target: String,
+    #[structopt(required = true, min_values = 2)]
+    /// Files to copy, where `profile:path` is a path on the server of a profile
+    pub paths: Vec<String>,
+    #[structopt(short, long)]
+    /// Private key to authenticate with
+    pub private_key: Option<String>,
+    #[structopt(short = "k", long)]
+    /// Alternative public key to use
+    pub public_key: Option<String>,
+    #[structopt(short, long, allow_hyphen_values = true)]
+    /// Extra SSH client options, quoted like in a shell
+    pub options: Option<String>,
+    #[structopt(short, long, conflicts_with_all = &["private-key", "public-key"])]
+    /// Generate a key pair for this transfer that is removed afterwards
+    pub ephemeral: bool,
+    #[structopt(short, long)]
+    /// Load the key and certificate into the running ssh-agent instead of passing them to ssh
+    pub agent: bool,
+    #[structopt(flatten)]
+    pub signing: SignArgs,
+    #[structopt(last = true)]
+    /// Other arguments for scp or rsync, given after --
+    pub arguments: Vec<String>,
 }
 
 #[derive(Debug, StructOpt)]
@@ -92,15 +306,44 @@ pub enum Profiles {
         address: String,
         /// Role to sign the public key as
         role: String,
+        #[structopt(long)]
+        /// Port the server listens on, defaults to 22
+        port: Option<u16>,
+        #[structopt(short = "J", long, conflicts_with = "jump")]
+        /// Jump host to connect through, as accepted by ssh -J
+        proxy_jump: Option<String>,
+        #[structopt(long, number_of_values = 1)]
+        /// Profile of a jump host to connect through with its own certificate, can be repeated in the order they are reached
+        jump: Vec<String>,
         #[structopt(short, long)]
         /// Private key to use for authentication
         private_key: Option<String>,
         #[structopt(short = "k", long)]
         /// Public key to be signed
         public_key: Option<String>,
-        #[structopt(short, long)]
-        /// Other options to pass to the ssh command
+        #[structopt(short, long, allow_hyphen_values = true)]
+        /// Other options to pass to the ssh command, quoted like in a shell
         options: Option<String>,
+        #[structopt(long)]
+        /// Path of the SSH CA on the Vault server, defaults to the configured path
+        path: Option<String>,
+        #[structopt(long)]
+        /// Vault Enterprise namespace to use, defaults to the configured namespace
+        namespace: Option<String>,
+        #[structopt(short, long)]
+        /// Generate a key pair for each session instead of using an existing one
+        ephemeral: bool,
+        #[structopt(short = "L", long = "local-forward", number_of_values = 1)]
+        /// Port to forward to a host from the server as [bind_address:]port:host:hostport, can be repeated
+        local_forwards: Vec<Forward>,
+        #[structopt(short = "R", long = "remote-forward", number_of_values = 1)]
+        /// Port on the server to forward to a local host as [bind_address:]port:host:hostport, can be repeated
+        remote_forwards: Vec<Forward>,
+        #[structopt(short = "D", long)]
+        /// Port to open a SOCKS proxy through the server on as [bind_address:]port
+        dynamic_forward: Option<DynamicForward>,
+        #[structopt(flatten)]
+        signing: SignArgs,
     },
     #[structopt(alias = "inspect")]
     /// Get the details about a profile
@@ -121,6 +364,18 @@ pub enum Profiles {
         #[structopt(short, long)]
         /// New IP address or FQDN to use
         address: Option<String>,
+        #[structopt(long)]
+        /// New port to connect to
+        port: Option<u16>,
+        #[structopt(short = "J", long)]
+        /// New jump host to connect through
+        proxy_jump: Option<String>,
+        #[structopt(long, number_of_values = 1, conflicts_with = "no-jump")]
+        /// New profiles of jump hosts to connect through, can be repeated in the order they are reached
+        jump: Vec<String>,
+        #[structopt(long)]
+        /// Stop connecting through the jump host profiles
+        no_jump: bool,
         #[structopt(short, long)]
         /// New role to use for signing
         role: Option<String>,
@@ -130,9 +385,52 @@ pub enum Profiles {
         #[structopt(short = "k", long)]
         /// New public key to sign
         public_key: Option<String>,
-        #[structopt(short, long)]
-        /// New options to pass to ssh
+        #[structopt(short, long, allow_hyphen_values = true)]
+        /// New options to pass to ssh, quoted like in a shell
         options: Option<String>,
+        #[structopt(long)]
+        /// New path of the SSH CA to use
+        path: Option<String>,
+        #[structopt(long)]
+        /// New Vault Enterprise namespace to use
+        namespace: Option<String>,
+        #[structopt(long)]
+        /// Whether to generate a key pair for each session
+        ephemeral: Option<bool>,
+        #[structopt(short = "L", long = "local-forward", number_of_values = 1)]
+        /// Local port forward to add as [bind_address:]port:host:hostport, can be repeated
+        local_forwards: Vec<Forward>,
+        #[structopt(short = "R", long = "remote-forward", number_of_values = 1)]
+        /// Remote port forward to add as [bind_address:]port:host:hostport, can be repeated
+        remote_forwards: Vec<Forward>,
+        #[structopt(short = "D", long)]
+        /// New port for the SOCKS proxy as [bind_address:]port
+        dynamic_forward: Option<DynamicForward>,
+        #[structopt(long)]
+        /// Remove all forwards and the SOCKS proxy before adding any new ones
+        clear_forwards: bool,
+        #[structopt(long)]
+        /// Remove all signing parameters before setting any new ones
+        clear_sign_options: bool,
+        #[structopt(flatten)]
+        signing: SignArgs,
+    },
+    /// Create profiles for the hosts in an OpenSSH client configuration
+    ///
+    /// Follows Include directives and applies settings from wildcard hosts
+    Import {
+        #[structopt(long)]
+        /// Path to the ssh client configuration to import
+        from_ssh_config: String,
+        #[structopt(short, long)]
+        /// Role to sign with for hosts that no mapping matches
+        role: Option<String>,
+        #[structopt(long = "role-map", number_of_values = 1, parse(from_str = parse_key_value))]
+        /// Role for hosts matching a pattern as pattern=role, can be repeated and the first match wins
+        role_map: Vec<(String, String)>,
+        #[structopt(short, long)]
+        /// Import without asking for confirmation
+        yes: bool,
     },
     /// Delete a profile
     Delete {
@@ -146,5 +444,171 @@ pub enum Profiles {
         #[structopt(short = "f", long)]
         /// Connect for SFTP instead of pure SSH
         sftp: bool,
+        #[structopt(short, long)]
+        /// Generate a key pair for this session even if the profile does not
+        ephemeral: bool,
+        #[structopt(short, long)]
+        /// Load the key and certificate into the running ssh-agent instead of passing them to ssh
+        agent: bool,
+        #[structopt(flatten)]
+        signing: SignArgs,
+        #[structopt(last = true)]
+        /// Command to run on the server instead of a shell, given after --
+        remote_command: Vec<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Host {
+    /// Sign the host's SSH keys so clients can verify it
+    ///
+    /// Certificates are written next to each key as `*-cert.pub`
+    Sign {
+        /// Role to sign the host keys with
+        role: String,
+        #[structopt(long, default_value = "/etc/ssh")]
+        /// Directory containing the ssh_host_*_key.pub files
+        keys_dir: String,
+        #[structopt(long)]
+        /// Comma separated principals to request, defaults to the host's FQDNs
+        principals: Option<String>,
+        #[structopt(long)]
+        /// Lifetime to request for the certificates, defaults to the role's TTL
+        ttl: Option<String>,
+        #[structopt(long)]
+        /// Add the HostCertificate directives to the sshd configuration instead of printing them
+        apply: bool,
+        #[structopt(long, default_value = "/etc/ssh/sshd_config")]
+        /// Path to the sshd configuration to update
+        sshd_config: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Ca {
+    /// Print the public key of the certificate authority
+    Show,
+    /// Trust host certificates signed by the certificate authority
+    ///
+    /// Adds or updates a `@cert-authority` entry in the known hosts file
+    Trust {
+        #[structopt(short, long, default_value = "*")]
+        /// Hosts to trust the certificate authority for, i.e. `*.example.com`
+        pattern: String,
+        #[structopt(long)]
+        /// Path to the known hosts file, defaults to ~/.ssh/known_hosts
+        known_hosts: Option<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Server {
+    /// Configure sshd to trust user certificates signed by the certificate authority
+    TrustCa {
+        #[structopt(long, default_value = "/etc/ssh/trusted-user-ca-keys.pem")]
+        /// File to write the CA public key to
+        ca_file: String,
+        #[structopt(long, default_value = "/etc/ssh/sshd_config")]
+        /// Path to the sshd configuration to update
+        sshd_config: String,
+        #[structopt(long)]
+        /// Directory of per-user principals files to set as the AuthorizedPrincipalsFile
+        principals_dir: Option<String>,
+        #[structopt(long = "principal", number_of_values = 1, parse(from_str = parse_key_value))]
+        /// Principals a user accepts as user=principal,principal, can be repeated
+        principals: Vec<(String, String)>,
+        #[structopt(long)]
+        /// Replace an existing TrustedUserCAKeys instead of adding the CA key to the file it names
+        force: bool,
+        #[structopt(long)]
+        /// Print the changes that would be made without writing them
+        dry_run: bool,
     },
 }
+
+#[derive(Debug, StructOpt)]
+pub enum Cache {
+    #[structopt(alias = "ls")]
+    /// List the cached certificates
+    List,
+    /// Remove cached certificates
+    Clear {
+        #[structopt(long)]
+        /// Only remove certificates that have expired
+        expired: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Cert {
+    /// Show the contents of a certificate
+    Inspect {
+        /// Certificate file to inspect, or - to read from stdin
+        file: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SshConfig {
+    /// Write a Host block for every profile to a file included from the ssh client configuration
+    ///
+    /// The file is overwritten every time, so it should not be edited by hand
+    Generate {
+        #[structopt(long)]
+        /// File to write the Host blocks to, defaults to ~/.ssh/config.d/vssh
+        output: Option<String>,
+        #[structopt(long)]
+        /// ssh client configuration that includes the file, defaults to ~/.ssh/config
+        ssh_config: Option<String>,
+        #[structopt(long)]
+        /// Only check that the file is included instead of adding an Include line
+        no_include: bool,
+        #[structopt(long)]
+        /// Print the changes that would be made without writing them
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+/// Parameters to request when signing, overriding the defaults of the role
+pub struct SignArgs {
+    #[structopt(long)]
+    /// Comma separated principals to request
+    principals: Option<String>,
+    #[structopt(long)]
+    /// Lifetime to request for the certificate, i.e. 5m or 1h
+    ttl: Option<String>,
+    #[structopt(long, possible_values = &["user", "host"])]
+    /// Type of certificate to request
+    cert_type: Option<String>,
+    #[structopt(long)]
+    /// Key ID to embed in the certificate
+    key_id: Option<String>,
+    #[structopt(long = "critical-option", number_of_values = 1, parse(from_str = parse_key_value))]
+    /// Critical option to request as key=value, can be repeated
+    critical_options: Vec<(String, String)>,
+    #[structopt(long = "extension", number_of_values = 1, parse(from_str = parse_key_value))]
+    /// Extension to request as key or key=value, can be repeated
+    extensions: Vec<(String, String)>,
+}
+
+impl From<SignArgs> for SignOptions {
+    fn from(args: SignArgs) -> Self {
+        SignOptions {
+            valid_principals: args.principals,
+            ttl: args.ttl,
+            cert_type: args.cert_type,
+            key_id: args.key_id,
+            critical_options: args.critical_options.into_iter().collect(),
+            extensions: args.extensions.into_iter().collect(),
+        }
+    }
+}
+
+/// Split a key=value pair, using an empty value if there is no separator
+fn parse_key_value(pair: &str) -> (String, String) {
+    let mut parts = pair.splitn(2, '=');
+    let key = parts.next().unwrap_or_default().to_string();
+    let value = parts.next().unwrap_or_default().to_string();
+    (key, value)
+}
@@ -1,10 +1,12 @@
-use crate::errors::ConfigError;
+use crate::errors::{ConfigError, ForwardError};
 use reqwest::Certificate;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::{read_to_string, File, OpenOptions};
 use std::io::prelude::*;
 use std::path::Path;
+use std::str::FromStr;
 use url::Url;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,28 +16,262 @@ pub struct Config {
     pub path: String,
     pub custom_ca: String,
     pub tls: bool,
+    pub namespace: Option<String>,
+    pub auth: Option<Auth>,
+    pub renew_threshold: Option<u64>,
+    pub cache_margin: Option<u64>,
     pub profiles: HashMap<String, Profile>,
 }
 
+/// Method used to obtain a token when a static one is not used
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum Auth {
+    AppRole {
+        mount: String,
+        role_id: String,
+        secret_id_file: Option<String>,
+        secret_id_env: Option<String>,
+    },
+    Userpass {
+        mount: String,
+        username: String,
+    },
+    Ldap {
+        mount: String,
+        username: String,
+    },
+    Oidc {
+        mount: String,
+        role: Option<String>,
+        port: u16,
+    },
+}
+
+impl Auth {
+    /// Name of the authentication method
+    pub fn method(&self) -> &'static str {
+        match self {
+            Auth::AppRole { .. } => "approle",
+            Auth::Userpass { .. } => "userpass",
+            Auth::Ldap { .. } => "ldap",
+            Auth::Oidc { .. } => "oidc",
+        }
+    }
+
+    /// Identifies who logging in with the method authenticates as, including the mount
+    pub fn identity(&self) -> String {
+        match self {
+            Auth::AppRole { mount, role_id, .. } => format!("approle:{}:{}", mount, role_id),
+            Auth::Userpass { mount, username } => format!("userpass:{}:{}", mount, username),
+            Auth::Ldap { mount, username } => format!("ldap:{}:{}", mount, username),
+            Auth::Oidc { mount, role, .. } => {
+                format!("oidc:{}:{}", mount, role.as_deref().unwrap_or_default())
+            }
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Profile {
     pub username: String,
     pub address: String,
+    pub port: Option<u16>,
+    pub proxy_jump: Option<String>,
+    /// Profiles of the jump hosts to connect through, each with its own certificate
+    #[serde(default)]
+    pub jump: Vec<String>,
     pub role: String,
     pub private_key: Option<String>,
     pub public_key: Option<String>,
     pub options: String,
+    pub path: Option<String>,
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub sign_options: SignOptions,
+    #[serde(default)]
+    pub ephemeral: bool,
+    #[serde(default)]
+    pub local_forwards: Vec<Forward>,
+    #[serde(default)]
+    pub remote_forwards: Vec<Forward>,
+    pub dynamic_forward: Option<DynamicForward>,
+}
+
+/// A port forwarded to a host through the connection, written as `[bind_address:]port:host:hostport`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Forward {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<String>,
+    pub port: u16,
+    pub host: String,
+    pub host_port: u16,
+}
+
+/// A SOCKS proxy opened by the connection, written as `[bind_address:]port`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DynamicForward {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<String>,
+    pub port: u16,
+}
+
+impl FromStr for Forward {
+    type Err = ForwardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = split_address(s);
+        let bind_address = match parts.len() {
+            3 => None,
+            4 => Some(parts.remove(0)),
+            _ => return Err(ForwardError::MalformedForward(s.to_string())),
+        };
+
+        Ok(Forward {
+            bind_address,
+            port: parse_port(&parts[0])?,
+            host: parts[1].clone(),
+            host_port: parse_port(&parts[2])?,
+        })
+    }
+}
+
+impl fmt::Display for Forward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(bind_address) = &self.bind_address {
+            write!(f, "{}:", bracket(bind_address))?;
+        }
+        write!(
+            f,
+            "{}:{}:{}",
+            self.port,
+            bracket(&self.host),
+            self.host_port
+        )
+    }
+}
+
+impl FromStr for DynamicForward {
+    type Err = ForwardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = split_address(s);
+        let bind_address = match parts.len() {
+            1 => None,
+            2 => Some(parts.remove(0)),
+            _ => return Err(ForwardError::MalformedDynamicForward(s.to_string())),
+        };
+
+        Ok(DynamicForward {
+            bind_address,
+            port: parse_port(&parts[0])?,
+        })
+    }
+}
+
+impl fmt::Display for DynamicForward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(bind_address) = &self.bind_address {
+            write!(f, "{}:", bracket(bind_address))?;
+        }
+        write!(f, "{}", self.port)
+    }
+}
+
+/// Split a forward on colons, where IPv6 addresses are enclosed in brackets
+fn split_address(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut bracketed = false;
+    for c in s.chars() {
+        match c {
+            '[' if !bracketed => bracketed = true,
+            ']' if bracketed => bracketed = false,
+            ':' if !bracketed => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+/// Enclose an IPv6 address in brackets like ssh expects
+fn bracket(address: &str) -> String {
+    if address.contains(':') {
+        format!("[{}]", address)
+    } else {
+        address.to_string()
+    }
+}
+
+fn parse_port(port: &str) -> Result<u16, ForwardError> {
+    port.parse()
+        .map_err(|_| ForwardError::InvalidPort(port.to_string()))
+}
+
+/// Parameters requested when signing a public key.
+/// Any that are not set use the defaults of the role.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SignOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_principals: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub critical_options: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extensions: HashMap<String, String>,
+}
+
+impl SignOptions {
+    /// Fill in any parameters that are not set from another set of options.
+    /// Critical options and extensions are combined, preferring the current values.
+    pub fn or(mut self, defaults: &SignOptions) -> Self {
+        self.valid_principals = self
+            .valid_principals
+            .or_else(|| defaults.valid_principals.clone());
+        self.ttl = self.ttl.or_else(|| defaults.ttl.clone());
+        self.cert_type = self.cert_type.or_else(|| defaults.cert_type.clone());
+        self.key_id = self.key_id.or_else(|| defaults.key_id.clone());
+
+        for (key, value) in &defaults.critical_options {
+            self.critical_options
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+        for (key, value) in &defaults.extensions {
+            self.extensions
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+
+        self
+    }
 }
 
 impl Config {
     /// Initializes a new configuration with the provided values.
-    pub fn new(server: String, token: String, path: String, custom_ca: String, tls: bool) -> Self {
+    pub fn new(
+        server: String,
+        token: String,
+        path: String,
+        custom_ca: String,
+        tls: bool,
+        namespace: Option<String>,
+        auth: Option<Auth>,
+    ) -> Self {
         Config {
             server,
             token,
             path,
             custom_ca,
             tls,
+            namespace,
+            auth,
+            renew_threshold: None,
+            cache_margin: None,
             profiles: HashMap::new(),
         }
     }
@@ -87,26 +323,185 @@ impl Config {
             }
         };
 
-        // Ensure non-empty token
-        if self.token == "" {
-            return Err(ConfigError::InvalidToken);
+        // Ensure non-empty token or a complete authentication method
+        match &self.auth {
+            None => {
+                if self.token.is_empty() {
+                    return Err(ConfigError::InvalidToken);
+                }
+            }
+            Some(Auth::AppRole {
+                mount,
+                role_id,
+                secret_id_file,
+                secret_id_env,
+            }) => {
+                if mount.is_empty() {
+                    return Err(ConfigError::InvalidAuthMethod("missing AppRole mount"));
+                } else if role_id.is_empty() {
+                    return Err(ConfigError::InvalidAuthMethod("missing AppRole role ID"));
+                } else if secret_id_file.is_none() && secret_id_env.is_none() {
+                    return Err(ConfigError::InvalidAuthMethod(
+                        "missing AppRole secret ID file or environment variable",
+                    ));
+                }
+            }
+            Some(Auth::Userpass { mount, username }) | Some(Auth::Ldap { mount, username }) => {
+                if mount.is_empty() {
+                    return Err(ConfigError::InvalidAuthMethod("missing mount"));
+                } else if username.is_empty() {
+                    return Err(ConfigError::InvalidAuthMethod("missing username"));
+                }
+            }
+            Some(Auth::Oidc { mount, .. }) => {
+                if mount.is_empty() {
+                    return Err(ConfigError::InvalidAuthMethod("missing mount"));
+                }
+            }
         }
 
         // Validate custom CA configuration if in use
-        if self.custom_ca != "" {
+        if !self.custom_ca.is_empty() {
             self.read_certificate()?;
         }
 
         Ok(())
     }
 
-    /// Read a PEM encoded public certificate
+    /// Read the PEM encoded public certificate of the custom CA
     pub fn read_certificate(&self) -> Result<Certificate, ConfigError> {
-        // Ensure exists
-        let mut raw_pem = Vec::new();
-        File::open(&self.custom_ca)?.read_to_end(&mut raw_pem)?;
+        read_certificate(&self.custom_ca)
+    }
+}
+
+/// Read a PEM encoded public certificate
+pub fn read_certificate(path: &str) -> Result<Certificate, ConfigError> {
+    // Ensure exists
+    let mut raw_pem = Vec::new();
+    File::open(path)?.read_to_end(&mut raw_pem)?;
+
+    // Parse PEM
+    Ok(Certificate::from_pem(&raw_pem)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forward(bind_address: Option<&str>, port: u16, host: &str, host_port: u16) -> Forward {
+        Forward {
+            bind_address: bind_address.map(String::from),
+            port,
+            host: host.to_string(),
+            host_port,
+        }
+    }
+
+    #[test]
+    fn parses_forwards() {
+        assert_eq!(
+            "8080:localhost:80".parse::<Forward>().unwrap(),
+            forward(None, 8080, "localhost", 80)
+        );
+        assert_eq!(
+            "127.0.0.1:8080:web.internal:80".parse::<Forward>().unwrap(),
+            forward(Some("127.0.0.1"), 8080, "web.internal", 80)
+        );
+        assert_eq!(
+            "*:8080:localhost:80".parse::<Forward>().unwrap(),
+            forward(Some("*"), 8080, "localhost", 80)
+        );
+    }
+
+    #[test]
+    fn parses_ipv6_forwards() {
+        assert_eq!(
+            "[::1]:6379:cache:6379".parse::<Forward>().unwrap(),
+            forward(Some("::1"), 6379, "cache", 6379)
+        );
+        assert_eq!(
+            "8080:[2001:db8::1]:80".parse::<Forward>().unwrap(),
+            forward(None, 8080, "2001:db8::1", 80)
+        );
+        assert_eq!(
+            "[::1]:2222:[fe80::1%eth0]:22".parse::<Forward>().unwrap(),
+            forward(Some("::1"), 2222, "fe80::1%eth0", 22)
+        );
+    }
+
+    #[test]
+    fn displays_forwards_like_ssh() {
+        for forward in &[
+            "8080:localhost:80",
+            "127.0.0.1:8080:localhost:80",
+            "[::1]:6379:cache:6379",
+            "[::1]:2222:[2001:db8::1]:22",
+        ] {
+            assert_eq!(forward.parse::<Forward>().unwrap().to_string(), *forward);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_forwards() {
+        for forward in &[
+            "8080",
+            "8080:localhost",
+            "a:b:c:d:e",
+            "::1:8080:localhost:80",
+        ] {
+            assert!(matches!(
+                forward.parse::<Forward>(),
+                Err(ForwardError::MalformedForward(_))
+            ));
+        }
+        for forward in &[
+            "http:localhost:80",
+            "8080:localhost:65536",
+            "-1:localhost:80",
+        ] {
+            assert!(matches!(
+                forward.parse::<Forward>(),
+                Err(ForwardError::InvalidPort(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn parses_dynamic_forwards() {
+        let parsed = "1080".parse::<DynamicForward>().unwrap();
+        assert_eq!(parsed.bind_address, None);
+        assert_eq!(parsed.port, 1080);
+
+        let parsed = "localhost:1080".parse::<DynamicForward>().unwrap();
+        assert_eq!(parsed.bind_address.as_deref(), Some("localhost"));
+
+        let parsed = "[::1]:1080".parse::<DynamicForward>().unwrap();
+        assert_eq!(parsed.bind_address.as_deref(), Some("::1"));
+        assert_eq!(parsed.to_string(), "[::1]:1080");
+    }
+
+    #[test]
+    fn rejects_malformed_dynamic_forwards() {
+        assert!(matches!(
+            "a:b:1080".parse::<DynamicForward>(),
+            Err(ForwardError::MalformedDynamicForward(_))
+        ));
+        assert!(matches!(
+            "socks".parse::<DynamicForward>(),
+            Err(ForwardError::InvalidPort(_))
+        ));
+    }
 
-        // Parse PEM
-        Ok(Certificate::from_pem(&raw_pem)?)
+    #[test]
+    fn serializes_forwards_without_bind_address() {
+        let encoded = serde_json::to_string(&forward(None, 8080, "localhost", 80)).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"port":8080,"host":"localhost","host_port":80}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Forward>(&encoded).unwrap(),
+            forward(None, 8080, "localhost", 80)
+        );
     }
 }
@@ -0,0 +1,95 @@
+use std::env;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Settings shared with the Vault CLI.
+/// These are read from its standard environment variables and token helper.
+#[derive(Default)]
+pub struct VaultEnvironment {
+    /// Server address from `VAULT_ADDR`
+    pub address: Option<String>,
+    /// Token from `VAULT_TOKEN`
+    pub token: Option<String>,
+    /// Path to a PEM encoded CA certificate from `VAULT_CACERT`
+    pub ca_cert: Option<String>,
+    /// Whether to skip TLS verification from `VAULT_SKIP_VERIFY`
+    pub skip_verify: Option<bool>,
+    /// Enterprise namespace from `VAULT_NAMESPACE`
+    pub namespace: Option<String>,
+}
+
+impl VaultEnvironment {
+    /// Read the settings from the environment.
+    /// The token helper may be slow, so it is only run by `helper_token` when needed.
+    pub fn load() -> Self {
+        VaultEnvironment {
+            address: variable("VAULT_ADDR"),
+            token: variable("VAULT_TOKEN"),
+            ca_cert: variable("VAULT_CACERT"),
+            skip_verify: variable("VAULT_SKIP_VERIFY").and_then(|value| parse_bool(&value)),
+            namespace: variable("VAULT_NAMESPACE"),
+        }
+    }
+
+    /// Whether any settings were found
+    pub fn is_empty(&self) -> bool {
+        self.address.is_none()
+            && self.token.is_none()
+            && self.ca_cert.is_none()
+            && self.skip_verify.is_none()
+            && self.namespace.is_none()
+    }
+}
+
+/// Read a non-empty environment variable
+fn variable(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Parse a boolean the same way as the Vault CLI
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" | "t" | "T" | "true" | "TRUE" | "True" => Some(true),
+        "0" | "f" | "F" | "false" | "FALSE" | "False" => Some(false),
+        _ => None,
+    }
+}
+
+/// Get the token stored by the Vault CLI's configured token helper.
+/// Falls back to `~/.vault-token` like the Vault CLI when no helper is configured.
+pub fn helper_token() -> Option<String> {
+    let home = dirs::home_dir()?;
+
+    let token = if let Some(helper) = token_helper(&home) {
+        let output = Command::new(helper).arg("get").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()?
+    } else {
+        read_to_string(home.join(".vault-token")).ok()?
+    };
+
+    Some(token.trim().to_string()).filter(|token| !token.is_empty())
+}
+
+/// Find the token helper in the Vault CLI configuration file.
+/// The file is located at `VAULT_CONFIG_PATH`, defaulting to `~/.vault`.
+fn token_helper(home: &Path) -> Option<String> {
+    let path = variable("VAULT_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".vault"));
+    let contents = read_to_string(path).ok()?;
+
+    // Only the token_helper attribute is needed, so the HCL is not fully parsed
+    contents.lines().find_map(|line| {
+        let mut parts = line.splitn(2, '=');
+        if parts.next()?.trim() != "token_helper" {
+            return None;
+        }
+
+        let value = parts.next()?.trim().trim_matches('"');
+        Some(value.to_string()).filter(|value| !value.is_empty())
+    })
+}
@@ -8,6 +8,8 @@ pub enum ConfigError {
     InvalidUrl(#[from] ParseError),
     #[error("Invalid authentication token")]
     InvalidToken,
+    #[error("Invalid authentication method: {0}")]
+    InvalidAuthMethod(&'static str),
     #[error("Configuration file does not exist")]
     NonExistentConfigFile,
     #[error("Failed to read from file: {0}")]
@@ -28,8 +30,116 @@ pub enum ApiError {
     UnknownRole,
     #[error("Invalid public key format")]
     InvalidPublicKey,
+    #[error("No secrets engine is mounted at '{0}'")]
+    MountNotFound(String),
+    #[error("Secrets engine is of type '{0}', expected 'ssh'")]
+    InvalidMountType(String),
+    #[error("No CA key is configured for the secrets engine at '{0}'")]
+    MissingCaKey(String),
+    #[error("Invalid login credentials")]
+    InvalidCredentials,
+    #[error("Request rejected: {0}")]
+    Rejected(String),
     #[error("Failed to send request: {0}")]
     SendFailure(#[from] reqwest::Error),
     #[error("An unknown error occurred")]
     UnknownError,
 }
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Environment variable '{0}' is not set")]
+    MissingEnvironmentVariable(String),
+    #[error("No secret ID source is configured")]
+    MissingSecretId,
+    #[error("Redirect URI '{0}' is not allowed by the OIDC role")]
+    RedirectNotAllowed(String),
+    #[error("Timed out waiting for the OIDC callback")]
+    CallbackTimeout,
+    #[error("Identity provider returned an error: {0}")]
+    ProviderError(String),
+    #[error("The session expired, run 'vssh login' to log in again")]
+    SessionExpired,
+    #[error("Failed to read from file: {0}")]
+    ReadError(#[from] io::Error),
+    #[error("Failed to decode JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Failed to send request: {0}")]
+    SendFailure(#[from] reqwest::Error),
+    #[error("{0}")]
+    ApiError(#[from] ApiError),
+}
+
+#[derive(Debug, Error)]
+pub enum SignError {
+    #[error("Failed to authenticate: {0}")]
+    AuthError(#[from] AuthError),
+    #[error("Invalid token, please ensure it is correct and try again")]
+    InvalidToken,
+    #[error("Failed to validate token: {0}")]
+    ValidateError(reqwest::Error),
+    #[error("Failed to sign public key: {0}")]
+    ApiError(ApiError),
+}
+
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("Failed to read from file: {0}")]
+    ReadError(#[from] io::Error),
+    #[error("Failed to decode JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum SshError {
+    #[error("Expected a key type followed by base64 encoded data")]
+    InvalidFormat,
+    #[error("Invalid base64 encoding: {0}")]
+    InvalidEncoding(#[from] base64::DecodeError),
+    #[error("Unexpected end of data")]
+    UnexpectedEnd,
+    #[error("Key type '{0}' does not match the encoded type '{1}'")]
+    MismatchedKeyType(String, String),
+    #[error("Unsupported certificate type '{0}'")]
+    UnsupportedCertificate(String),
+    #[error("Expected a private key in the OpenSSH format")]
+    InvalidPrivateKey,
+    #[error("Encrypted private keys are not supported, add the key with ssh-add instead")]
+    EncryptedKey,
+    #[error("Unsupported key type '{0}'")]
+    UnsupportedKey(String),
+}
+
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("Environment variable 'SSH_AUTH_SOCK' is not set, is an agent running?")]
+    MissingSocket,
+    #[error("Failed to communicate with the agent: {0}")]
+    IoError(#[from] io::Error),
+    #[error("The agent refused the request")]
+    Refused,
+    #[error("{0}")]
+    SshError(#[from] SshError),
+}
+
+#[derive(Debug, Error)]
+pub enum ForwardError {
+    #[error("Expected [bind_address:]port:host:hostport, got '{0}'")]
+    MalformedForward(String),
+    #[error("Expected [bind_address:]port, got '{0}'")]
+    MalformedDynamicForward(String),
+    #[error("Invalid port '{0}'")]
+    InvalidPort(String),
+}
+
+#[derive(Debug, Error)]
+pub enum SshConfigError {
+    #[error("Failed to read '{0}': {1}")]
+    ReadError(String, io::Error),
+    #[error("Unterminated quote on line {1} of '{0}'")]
+    UnterminatedQuote(String, usize),
+    #[error("Invalid port '{1}' for host '{0}'")]
+    InvalidPort(String, String),
+    #[error("Includes are nested more than {0} levels deep")]
+    IncludeDepth(usize),
+}
@@ -1,15 +1,24 @@
 mod api;
+mod auth;
+mod cache;
 mod cli;
 mod config;
+mod environment;
 mod errors;
+mod ssh;
+mod ssh_config;
+mod sshd;
 mod subcommands;
 mod util;
 
 use api::ApiClient;
-use cli::{Command, Opts, Profiles};
+use cli::{Ca, Cache, Cert, Command, Host, Opts, Profiles, Server, SshConfig};
 use config::Config;
+use environment::VaultEnvironment;
+use errors::SignError;
 use std::path::Path;
 use structopt::StructOpt;
+use subcommands::Transport;
 use util::fail;
 
 #[tokio::main]
@@ -20,6 +29,9 @@ async fn main() {
     // Add header to command
     leg::head("vssh", Some("🔒"), Some(util::VERSION));
 
+    // Settings of the Vault CLI are shared by every client
+    let environment = VaultEnvironment::load();
+
     match cli.cmd {
         Command::RepairConfig => {
             subcommands::repair_config(cli.config);
@@ -31,6 +43,16 @@ async fn main() {
             token,
             path,
             custom_ca,
+            namespace,
+            auth_method,
+            auth_mount,
+            role_id,
+            secret_id_file,
+            secret_id_env,
+            username,
+            oidc_role,
+            callback_port,
+            import_vault_env,
         } => {
             if non_interactive {
                 subcommands::setup::noninteractive(
@@ -39,20 +61,63 @@ async fn main() {
                     token.unwrap_or_default(),
                     path.unwrap_or_default(),
                     custom_ca.unwrap_or_default(),
+                    namespace,
+                    auth_method.unwrap_or_else(|| String::from("token")),
+                    auth_mount,
+                    role_id.unwrap_or_default(),
+                    secret_id_file,
+                    secret_id_env,
+                    username,
+                    oidc_role,
+                    callback_port,
+                    import_vault_env,
                 )
+                .await
             } else {
-                subcommands::setup::interactive()
+                subcommands::setup::interactive().await
             }
         }
+        Command::Login {
+            method,
+            mount,
+            username,
+            role,
+            port,
+        } => {
+            let config = load_config(cli.config);
+            subcommands::login(
+                &config,
+                &environment,
+                cli.namespace,
+                method,
+                mount,
+                username,
+                role,
+                port,
+            )
+            .await;
+        }
         Command::List => {
             let config = load_config(cli.config);
-            let client = initialize_api(&config).await;
+            let client = initialize_api(&config, &environment, cli.path, cli.namespace).await;
             subcommands::list(&client).await
         }
-        Command::Sign { role, key, output } => {
+        Command::Sign {
+            role,
+            key,
+            output,
+            signing,
+        } => {
             let config = load_config(cli.config);
-            let client = initialize_api(&config).await;
-            subcommands::sign(&client, role, key, output.unwrap_or_default()).await;
+            let client = initialize_api(&config, &environment, cli.path, cli.namespace).await;
+            subcommands::sign(
+                &client,
+                role,
+                key,
+                output.unwrap_or_default(),
+                signing.into(),
+            )
+            .await;
         }
         Command::Connect {
             role,
@@ -61,39 +126,310 @@ async fn main() {
             server,
             sftp,
             options,
+            ephemeral,
+            agent,
+            signing,
+            remote_command,
         } => {
             let config = load_config(cli.config);
-            let client = initialize_api(&config).await;
-            subcommands::connect(
+            let options = subcommands::parse_options(&options.unwrap_or_default());
+            let transport = if sftp {
+                Transport::Sftp
+            } else {
+                Transport::Ssh
+            };
+            let arguments = transport.destination(server, remote_command);
+            let client = initialize_api(&config, &environment, cli.path, cli.namespace).await;
+            let status = subcommands::connect(
                 &client,
                 role,
                 private_key,
                 public_key,
-                server,
-                sftp,
-                options.unwrap_or_default(),
+                transport,
+                options,
+                arguments,
+                None,
+                ephemeral,
+                agent,
+                signing.into(),
+                config.cache_margin.unwrap_or(cache::DEFAULT_CACHE_MARGIN),
+            )
+            .await;
+            subcommands::exit_with(status);
+        }
+        Command::Scp(transfer) => {
+            let config = load_config(cli.config);
+            subcommands::copy(
+                &config,
+                Transport::Scp,
+                transfer,
+                cli.path,
+                cli.namespace,
+                |path, namespace| try_initialize_api(&config, &environment, path, namespace),
+            )
+            .await;
+        }
+        Command::Rsync(transfer) => {
+            let config = load_config(cli.config);
+            subcommands::copy(
+                &config,
+                Transport::Rsync,
+                transfer,
+                cli.path,
+                cli.namespace,
+                |path, namespace| try_initialize_api(&config, &environment, path, namespace),
+            )
+            .await;
+        }
+        Command::AgentAdd {
+            role,
+            private_key,
+            public_key,
+            ephemeral,
+            signing,
+        } => {
+            let config = load_config(cli.config);
+            let client = initialize_api(&config, &environment, cli.path, cli.namespace).await;
+            subcommands::agent_add(
+                &client,
+                role,
+                private_key,
+                public_key,
+                ephemeral,
+                signing.into(),
+                config.cache_margin.unwrap_or(cache::DEFAULT_CACHE_MARGIN),
+            )
+            .await;
+        }
+        Command::Agent {
+            role,
+            private_key,
+            public_key,
+            ephemeral,
+            socket,
+            signing,
+        } => {
+            let config = load_config(cli.config);
+            let client = initialize_api(&config, &environment, cli.path, cli.namespace).await;
+            // Tokens from the environment cannot be replaced by logging in again
+            let auth = Some(config.auth.clone()).filter(|_| environment.token.is_none());
+            subcommands::agent(
+                client,
+                auth,
+                role,
+                private_key,
+                public_key,
+                ephemeral,
+                socket,
+                signing.into(),
+                config.cache_margin.unwrap_or(cache::DEFAULT_CACHE_MARGIN),
+            )
+            .await;
+        }
+        Command::Proxy {
+            host,
+            port,
+            role,
+            profile,
+            private_key,
+            public_key,
+            check,
+            signing,
+        } => {
+            let config = load_config(cli.config);
+            let cache_margin = config.cache_margin.unwrap_or(cache::DEFAULT_CACHE_MARGIN);
+            let sign_options: config::SignOptions = signing.into();
+
+            // An explicit role takes precedence, otherwise the profile's settings are the defaults
+            match role {
+                Some(role) => {
+                    let client =
+                        initialize_api(&config, &environment, cli.path, cli.namespace).await;
+                    subcommands::proxy(
+                        &client,
+                        host,
+                        port,
+                        role,
+                        private_key,
+                        public_key,
+                        false,
+                        check,
+                        sign_options,
+                        cache_margin,
+                    )
+                    .await;
+                }
+                None => {
+                    let profile = subcommands::find_profile(&config, profile.as_deref(), &host);
+                    let path = cli.path.or_else(|| profile.path.clone());
+                    let namespace = cli.namespace.or_else(|| profile.namespace.clone());
+                    let client = initialize_api(&config, &environment, path, namespace).await;
+
+                    // Keys given on the command line are used even if the profile is ephemeral
+                    let ephemeral =
+                        profile.ephemeral && private_key.is_none() && public_key.is_none();
+                    subcommands::proxy(
+                        &client,
+                        host,
+                        port,
+                        profile.role.clone(),
+                        private_key.or_else(|| profile.private_key.clone()),
+                        public_key.or_else(|| profile.public_key.clone()),
+                        ephemeral,
+                        check,
+                        sign_options.or(&profile.sign_options),
+                        cache_margin,
+                    )
+                    .await;
+                }
+            }
+        }
+        Command::Exec {
+            profiles,
+            parallel,
+            fail_fast,
+            remote_command,
+        } => {
+            let config = load_config(cli.config);
+            subcommands::exec(
+                &config,
+                profiles,
+                cli.path,
+                cli.namespace,
+                remote_command,
+                parallel,
+                fail_fast,
+                config.cache_margin.unwrap_or(cache::DEFAULT_CACHE_MARGIN),
+                |path, namespace| try_initialize_api(&config, &environment, path, namespace),
             )
             .await;
         }
+        Command::Tunnel { name, retry_delay } => {
+            let config = load_config(cli.config);
+            subcommands::tunnel(
+                &config,
+                name,
+                cli.path,
+                cli.namespace,
+                retry_delay,
+                |path, namespace| try_initialize_api(&config, &environment, path, namespace),
+            )
+            .await;
+        }
+        Command::Host(h) => match h {
+            Host::Sign {
+                role,
+                keys_dir,
+                principals,
+                ttl,
+                apply,
+                sshd_config,
+            } => {
+                let config = load_config(cli.config);
+                let client = initialize_api(&config, &environment, cli.path, cli.namespace).await;
+                subcommands::host::sign(
+                    &client,
+                    role,
+                    keys_dir,
+                    principals,
+                    ttl,
+                    apply,
+                    sshd_config,
+                )
+                .await;
+            }
+        },
+        Command::Ca(c) => {
+            let config = load_config(cli.config);
+            let client = build_api(&config, &environment, cli.path, cli.namespace);
+            match c {
+                Ca::Show => subcommands::ca::show(&client).await,
+                Ca::Trust {
+                    pattern,
+                    known_hosts,
+                } => subcommands::ca::trust(&client, pattern, known_hosts).await,
+            }
+        }
+        Command::Server(s) => match s {
+            Server::TrustCa {
+                ca_file,
+                sshd_config,
+                principals_dir,
+                principals,
+                force,
+                dry_run,
+            } => {
+                let config = load_config(cli.config);
+                let client = build_api(&config, &environment, cli.path, cli.namespace);
+                subcommands::server::trust_ca(
+                    &client,
+                    ca_file,
+                    sshd_config,
+                    principals_dir,
+                    principals,
+                    force,
+                    dry_run,
+                )
+                .await;
+            }
+        },
+        Command::Cache(c) => match c {
+            Cache::List => subcommands::cache::list(),
+            Cache::Clear { expired } => subcommands::cache::clear(expired),
+        },
+        Command::Cert(c) => match c {
+            Cert::Inspect { file } => subcommands::cert::inspect(file),
+        },
+        Command::SshConfig(s) => match s {
+            SshConfig::Generate {
+                output,
+                ssh_config,
+                no_include,
+                dry_run,
+            } => {
+                let config = load_config(cli.config);
+                subcommands::ssh_config::generate(&config, output, ssh_config, no_include, dry_run);
+            }
+        },
         Command::Profiles(p) => match p {
             Profiles::Create {
                 name,
                 username,
                 address,
+                port,
+                proxy_jump,
+                jump,
                 role,
                 private_key,
                 public_key,
                 options,
+                path,
+                namespace,
+                ephemeral,
+                local_forwards,
+                remote_forwards,
+                dynamic_forward,
+                signing,
             } => {
                 let config = load_config(cli.config);
                 subcommands::profiles::create(
                     name,
                     username,
                     address,
+                    port,
+                    proxy_jump,
+                    jump,
                     role,
                     private_key,
                     public_key,
                     options.unwrap_or_default(),
+                    path,
+                    namespace,
+                    ephemeral,
+                    local_forwards,
+                    remote_forwards,
+                    dynamic_forward,
+                    signing.into(),
                     config,
                 );
             }
@@ -109,36 +445,110 @@ async fn main() {
                 name,
                 username,
                 address,
+                port,
+                proxy_jump,
+                jump,
+                no_jump,
                 role,
                 private_key,
                 public_key,
                 options,
+                path,
+                namespace,
+                ephemeral,
+                local_forwards,
+                remote_forwards,
+                dynamic_forward,
+                clear_forwards,
+                clear_sign_options,
+                signing,
             } => {
                 let config = load_config(cli.config);
                 subcommands::profiles::update(
                     name,
                     username,
                     address,
+                    port,
+                    proxy_jump,
+                    jump,
+                    no_jump,
                     role,
                     private_key,
                     public_key,
                     options,
+                    path,
+                    namespace,
+                    ephemeral,
+                    local_forwards,
+                    remote_forwards,
+                    dynamic_forward,
+                    clear_forwards,
+                    clear_sign_options,
+                    signing.into(),
                     config,
                 );
             }
+            Profiles::Import {
+                from_ssh_config,
+                role,
+                role_map,
+                yes,
+            } => {
+                let config = load_config(cli.config);
+                subcommands::profiles::import(from_ssh_config, role, role_map, yes, config);
+            }
             Profiles::Delete { name } => {
                 let config = load_config(cli.config);
                 subcommands::profiles::delete(name, config);
             }
-            Profiles::Connect { name, sftp } => {
+            Profiles::Connect {
+                name,
+                sftp,
+                ephemeral,
+                agent,
+                signing,
+                remote_command,
+            } => {
                 let config = load_config(cli.config);
-                let client = initialize_api(&config).await;
-                subcommands::profiles::connect(name, sftp, &client, &config).await;
+                subcommands::profiles::connect(
+                    name,
+                    if sftp {
+                        Transport::Sftp
+                    } else {
+                        Transport::Ssh
+                    },
+                    remote_command,
+                    ephemeral,
+                    agent,
+                    signing.into(),
+                    cli.path,
+                    cli.namespace,
+                    &config,
+                    |path, namespace| try_initialize_api(&config, &environment, path, namespace),
+                )
+                .await;
             }
         },
     };
 }
 
+/// Generate a client from the configuration without authenticating
+fn build_api(
+    cfg: &Config,
+    environment: &VaultEnvironment,
+    path: Option<String>,
+    namespace: Option<String>,
+) -> ApiClient {
+    let mut client = ApiClient::from_config(cfg, environment);
+    if let Some(path) = path {
+        client = client.with_path(&path);
+    }
+    if let Some(namespace) = namespace {
+        client = client.with_namespace(&namespace);
+    }
+    client
+}
+
 /// Load configuration file and handle errors
 fn load_config(file: Option<String>) -> Config {
     // Attempt to read config file
@@ -160,19 +570,45 @@ fn load_config(file: Option<String>) -> Config {
 }
 
 /// Initialize the API client to interact with Vault
-async fn initialize_api(cfg: &Config) -> ApiClient {
-    // Generate a client from the configuration
-    let client = ApiClient::from_config(cfg);
+/// The SSH CA path and namespace from the configuration are used unless others are provided
+async fn initialize_api(
+    cfg: &Config,
+    environment: &VaultEnvironment,
+    path: Option<String>,
+    namespace: Option<String>,
+) -> ApiClient {
+    match try_initialize_api(cfg, environment, path, namespace).await {
+        Ok(client) => client,
+        Err(e) => fail(&e.to_string()),
+    }
+}
+
+/// Initialize the API client like `initialize_api`, returning errors instead of exiting
+async fn try_initialize_api(
+    cfg: &Config,
+    environment: &VaultEnvironment,
+    path: Option<String>,
+    namespace: Option<String>,
+) -> Result<ApiClient, SignError> {
+    let mut client = build_api(cfg, environment, path, namespace);
+
+    // Use a cached token or log in if necessary, unless a token is provided by the environment
+    if environment.token.is_none() {
+        auth::authenticate(&mut client, cfg.auth.as_ref()).await?;
+    }
 
     // Ensure able to access API
-    match client.validate().await {
-        Ok(status) => {
-            if !status {
-                fail("Invalid token, please ensure it is correct and try again");
-            }
-        }
-        Err(e) => fail(&format!("Failed to validate token: {}", e)),
+    let info = match client.validate().await {
+        Ok(Some(info)) => info,
+        Ok(None) => return Err(SignError::InvalidToken),
+        Err(e) => return Err(SignError::ValidateError(e)),
     };
 
-    client
+    // Keep the token from expiring mid-workflow
+    let threshold = cfg.renew_threshold.unwrap_or(auth::DEFAULT_RENEW_THRESHOLD);
+    if let Err(e) = auth::renew(&client, &info, threshold).await {
+        leg::warn(&format!("Failed to renew token: {}", e), None, None);
+    }
+
+    Ok(client)
 }
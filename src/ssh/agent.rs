@@ -0,0 +1,342 @@
+use super::private_key::PrivateKey;
+use super::wire::{Reader, Writer};
+use crate::errors::{AgentError, SshError};
+use std::env;
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENT_SUCCESS: u8 = 6;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+const SSH_AGENTC_ADD_ID_CONSTRAINED: u8 = 25;
+const SSH_AGENT_CONSTRAIN_LIFETIME: u8 = 1;
+
+/// Messages larger than this are rejected by OpenSSH's agent
+pub const MAX_MESSAGE_SIZE: u32 = 256 * 1024;
+
+/// A request received by an agent
+pub enum AgentRequest {
+    Identities,
+    Sign {
+        key: Vec<u8>,
+        data: Vec<u8>,
+        flags: u32,
+    },
+    Unsupported,
+}
+
+impl AgentRequest {
+    /// Parse a message without its length prefix
+    pub fn parse(message: &[u8]) -> Result<Self, SshError> {
+        let (&kind, body) = message.split_first().ok_or(SshError::UnexpectedEnd)?;
+        let mut reader = Reader::new(body);
+        match kind {
+            SSH_AGENTC_REQUEST_IDENTITIES => Ok(AgentRequest::Identities),
+            SSH_AGENTC_SIGN_REQUEST => Ok(AgentRequest::Sign {
+                key: reader.string()?.to_vec(),
+                data: reader.string()?.to_vec(),
+                flags: reader.u32()?,
+            }),
+            _ => Ok(AgentRequest::Unsupported),
+        }
+    }
+}
+
+/// Reply listing public keys or certificates along with their comments
+pub fn identities_answer(identities: &[(Vec<u8>, String)]) -> Vec<u8> {
+    let mut message = Writer::new();
+    message
+        .raw(&[SSH_AGENT_IDENTITIES_ANSWER])
+        .u32(identities.len() as u32);
+    for (blob, comment) in identities {
+        message.string(blob).string(comment.as_bytes());
+    }
+    message.into_bytes()
+}
+
+/// Reply with a signature in the wire format
+pub fn sign_response(signature: &[u8]) -> Vec<u8> {
+    let mut message = Writer::new();
+    message.raw(&[SSH_AGENT_SIGN_RESPONSE]).string(signature);
+    message.into_bytes()
+}
+
+/// Reply indicating the request failed or is not supported
+pub fn failure() -> Vec<u8> {
+    vec![SSH_AGENT_FAILURE]
+}
+
+/// Client for the agent protocol described in draft-miller-ssh-agent
+pub struct AgentClient {
+    stream: UnixStream,
+}
+
+impl AgentClient {
+    /// Connect to the agent listening at `SSH_AUTH_SOCK`
+    pub fn connect() -> Result<Self, AgentError> {
+        let path = env::var_os("SSH_AUTH_SOCK").ok_or(AgentError::MissingSocket)?;
+        AgentClient::connect_to(&path)
+    }
+
+    /// Connect to the agent listening at a path
+    pub fn connect_to<P: AsRef<OsStr> + ?Sized>(path: &P) -> Result<Self, AgentError> {
+        Ok(AgentClient {
+            stream: UnixStream::connect(path.as_ref())?,
+        })
+    }
+
+    /// Ask the agent to sign data with the key it holds for a public key
+    pub fn sign(&mut self, key: &[u8], data: &[u8], flags: u32) -> Result<Vec<u8>, AgentError> {
+        let mut message = Writer::new();
+        message
+            .raw(&[SSH_AGENTC_SIGN_REQUEST])
+            .string(key)
+            .string(data)
+            .u32(flags);
+
+        let reply = self.request(&message.into_bytes())?;
+        match reply.split_first() {
+            Some((&SSH_AGENT_SIGN_RESPONSE, body)) => Ok(Reader::new(body).string()?.to_vec()),
+            _ => Err(AgentError::Refused),
+        }
+    }
+
+    /// Add a private key along with its certificate.
+    /// The agent removes them once the lifetime in seconds has passed, if one is given.
+    pub fn add_certificate(
+        &mut self,
+        key: &PrivateKey,
+        certificate: &[u8],
+        comment: &str,
+        lifetime: Option<u32>,
+    ) -> Result<(), AgentError> {
+        let certificate_type = Reader::new(certificate).text()?;
+
+        let mut message = Writer::new();
+        message
+            .raw(&[SSH_AGENTC_ADD_ID_CONSTRAINED])
+            .string(certificate_type.as_bytes())
+            .string(certificate);
+        for field in key.certificate_fields() {
+            message.string(field);
+        }
+        message.string(comment.as_bytes());
+        if let Some(lifetime) = lifetime {
+            message.raw(&[SSH_AGENT_CONSTRAIN_LIFETIME]).u32(lifetime);
+        }
+
+        match self.request(&message.into_bytes())?.first() {
+            Some(&SSH_AGENT_SUCCESS) => Ok(()),
+            // Anything else, usually SSH_AGENT_FAILURE, means the key was not added
+            _ => Err(AgentError::Refused),
+        }
+    }
+
+    /// Send a message and wait for the reply
+    fn request(&mut self, message: &[u8]) -> Result<Vec<u8>, AgentError> {
+        let mut framed = Writer::new();
+        framed.string(message);
+        self.stream.write_all(&framed.into_bytes())?;
+
+        let mut length = [0u8; 4];
+        self.stream.read_exact(&mut length)?;
+        let length = u32::from_be_bytes(length);
+        if length > MAX_MESSAGE_SIZE {
+            return Err(AgentError::Refused);
+        }
+
+        let mut reply = vec![0u8; length as usize];
+        self.stream.read_exact(&mut reply)?;
+        Ok(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread::{self, JoinHandle};
+
+    const CERTIFICATE_TYPE: &str = "ssh-ed25519-cert-v01@openssh.com";
+
+    /// An agent that answers a single request with a reply, returning the request it received
+    fn stub_agent(reply: Vec<u8>) -> (tempfile::TempDir, JoinHandle<Vec<u8>>) {
+        let directory = tempfile::tempdir().unwrap();
+        let listener = UnixListener::bind(directory.path().join("agent.sock")).unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut length = [0u8; 4];
+            stream.read_exact(&mut length).unwrap();
+            let mut request = vec![0u8; u32::from_be_bytes(length) as usize];
+            stream.read_exact(&mut request).unwrap();
+
+            let mut framed = Writer::new();
+            framed.string(&reply);
+            stream.write_all(&framed.into_bytes()).unwrap();
+            request
+        });
+        (directory, handle)
+    }
+
+    fn private_key() -> PrivateKey {
+        PrivateKey {
+            key_type: String::from("ssh-ed25519"),
+            fields: vec![vec![1; 32], vec![2; 64]],
+        }
+    }
+
+    fn certificate() -> Vec<u8> {
+        let mut certificate = Writer::new();
+        certificate
+            .string(CERTIFICATE_TYPE.as_bytes())
+            .string(b"nonce")
+            .string(&[1; 32]);
+        certificate.into_bytes()
+    }
+
+    #[test]
+    fn parses_identities_request() {
+        assert!(matches!(
+            AgentRequest::parse(&[SSH_AGENTC_REQUEST_IDENTITIES]),
+            Ok(AgentRequest::Identities)
+        ));
+    }
+
+    #[test]
+    fn parses_sign_request() {
+        let mut message = Writer::new();
+        message
+            .raw(&[SSH_AGENTC_SIGN_REQUEST])
+            .string(b"key")
+            .string(b"data")
+            .u32(2);
+
+        match AgentRequest::parse(&message.into_bytes()) {
+            Ok(AgentRequest::Sign { key, data, flags }) => {
+                assert_eq!(key, b"key");
+                assert_eq!(data, b"data");
+                assert_eq!(flags, 2);
+            }
+            _ => panic!("expected a sign request"),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_requests() {
+        assert!(matches!(
+            AgentRequest::parse(&[]),
+            Err(SshError::UnexpectedEnd)
+        ));
+
+        let mut message = Writer::new();
+        message.raw(&[SSH_AGENTC_SIGN_REQUEST]).string(b"key");
+        assert!(matches!(
+            AgentRequest::parse(&message.into_bytes()),
+            Err(SshError::UnexpectedEnd)
+        ));
+    }
+
+    #[test]
+    fn ignores_unsupported_requests() {
+        assert!(matches!(
+            AgentRequest::parse(&[SSH_AGENTC_ADD_ID_CONSTRAINED, 0, 0]),
+            Ok(AgentRequest::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn encodes_identities_answer() {
+        let answer = identities_answer(&[
+            (vec![1, 2], String::from("first")),
+            (vec![3], String::new()),
+        ]);
+
+        let mut expected = vec![SSH_AGENT_IDENTITIES_ANSWER, 0, 0, 0, 2];
+        expected.extend_from_slice(&[0, 0, 0, 2, 1, 2, 0, 0, 0, 5]);
+        expected.extend_from_slice(b"first");
+        expected.extend_from_slice(&[0, 0, 0, 1, 3, 0, 0, 0, 0]);
+        assert_eq!(answer, expected);
+    }
+
+    #[test]
+    fn encodes_sign_response_and_failure() {
+        assert_eq!(
+            sign_response(&[9, 8]),
+            [SSH_AGENT_SIGN_RESPONSE, 0, 0, 0, 2, 9, 8]
+        );
+        assert_eq!(failure(), [SSH_AGENT_FAILURE]);
+    }
+
+    #[test]
+    fn adds_certificate_with_lifetime() {
+        let (directory, agent) = stub_agent(vec![SSH_AGENT_SUCCESS]);
+        let mut client = AgentClient::connect_to(&directory.path().join("agent.sock")).unwrap();
+        client
+            .add_certificate(&private_key(), &certificate(), "vssh", Some(300))
+            .unwrap();
+
+        let mut expected = Writer::new();
+        expected
+            .raw(&[SSH_AGENTC_ADD_ID_CONSTRAINED])
+            .string(CERTIFICATE_TYPE.as_bytes())
+            .string(&certificate())
+            .string(&[1; 32])
+            .string(&[2; 64])
+            .string(b"vssh")
+            .raw(&[SSH_AGENT_CONSTRAIN_LIFETIME])
+            .u32(300);
+        assert_eq!(agent.join().unwrap(), expected.into_bytes());
+    }
+
+    #[test]
+    fn adds_certificate_without_lifetime() {
+        let (directory, agent) = stub_agent(vec![SSH_AGENT_SUCCESS]);
+        let mut client = AgentClient::connect_to(&directory.path().join("agent.sock")).unwrap();
+        client
+            .add_certificate(&private_key(), &certificate(), "vssh", None)
+            .unwrap();
+
+        let mut expected = Writer::new();
+        expected
+            .raw(&[SSH_AGENTC_ADD_ID_CONSTRAINED])
+            .string(CERTIFICATE_TYPE.as_bytes())
+            .string(&certificate())
+            .string(&[1; 32])
+            .string(&[2; 64])
+            .string(b"vssh");
+        assert_eq!(agent.join().unwrap(), expected.into_bytes());
+    }
+
+    #[test]
+    fn reports_refused_certificate() {
+        let (directory, agent) = stub_agent(failure());
+        let mut client = AgentClient::connect_to(&directory.path().join("agent.sock")).unwrap();
+        let result = client.add_certificate(&private_key(), &certificate(), "vssh", None);
+
+        assert!(matches!(result, Err(AgentError::Refused)));
+        agent.join().unwrap();
+    }
+
+    #[test]
+    fn signs_through_agent() {
+        let (directory, agent) = stub_agent(sign_response(b"signature"));
+        let mut client = AgentClient::connect_to(&directory.path().join("agent.sock")).unwrap();
+        let signature = client.sign(b"key", b"data", 4).unwrap();
+        assert_eq!(signature, b"signature");
+
+        let request = agent.join().unwrap();
+        match AgentRequest::parse(&request) {
+            Ok(AgentRequest::Sign { key, data, flags }) => {
+                assert_eq!(key, b"key");
+                assert_eq!(data, b"data");
+                assert_eq!(flags, 4);
+            }
+            _ => panic!("expected a sign request"),
+        }
+    }
+}
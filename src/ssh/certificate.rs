@@ -0,0 +1,227 @@
+use super::key::fingerprint_blob;
+use super::wire::{decode_line, Reader};
+use crate::errors::SshError;
+
+const CERTIFICATE_SUFFIX: &str = "-cert-v01@openssh.com";
+
+/// Validity timestamp meaning the certificate never expires
+pub const FOREVER: u64 = u64::MAX;
+
+/// An OpenSSH certificate as described in PROTOCOL.certkeys
+pub struct Certificate {
+    pub key_type: String,
+    pub serial: u64,
+    pub cert_type: CertificateType,
+    pub key_id: String,
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    pub critical_options: Vec<(String, String)>,
+    pub extensions: Vec<(String, String)>,
+    /// Key type of the CA that signed the certificate
+    pub ca_key_type: String,
+    /// SHA256 fingerprint of the CA that signed the certificate
+    pub ca_fingerprint: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CertificateType {
+    User,
+    Host,
+    Unknown(u32),
+}
+
+impl Certificate {
+    /// Parse a certificate in the format written to `*-cert.pub` files
+    pub fn parse(line: &str) -> Result<Self, SshError> {
+        let (key_type, blob) = decode_line(line)?;
+        let base_type = match key_type.strip_suffix(CERTIFICATE_SUFFIX) {
+            Some(base_type) => base_type,
+            None => return Err(SshError::UnsupportedCertificate(key_type)),
+        };
+
+        // Skip the type and nonce
+        let mut reader = Reader::new(&blob);
+        reader.string()?;
+        reader.string()?;
+
+        // Skip over the public key, whose fields depend on its type
+        let fields = match base_type {
+            "ssh-ed25519" => 1,
+            "ssh-rsa" | "sk-ssh-ed25519@openssh.com" => 2,
+            "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" | "ecdsa-sha2-nistp521" => 2,
+            "sk-ecdsa-sha2-nistp256@openssh.com" => 3,
+            "ssh-dss" => 4,
+            _ => return Err(SshError::UnsupportedCertificate(key_type.clone())),
+        };
+        for _ in 0..fields {
+            reader.string()?;
+        }
+
+        let serial = reader.u64()?;
+        let cert_type = match reader.u32()? {
+            1 => CertificateType::User,
+            2 => CertificateType::Host,
+            other => CertificateType::Unknown(other),
+        };
+        let key_id = reader.text()?;
+
+        let mut principals = Vec::new();
+        let mut encoded = Reader::new(reader.string()?);
+        while !encoded.is_empty() {
+            principals.push(encoded.text()?);
+        }
+
+        let valid_after = reader.u64()?;
+        let valid_before = reader.u64()?;
+        let critical_options = parse_options(reader.string()?)?;
+        let extensions = parse_options(reader.string()?)?;
+
+        // Skip the reserved field
+        reader.string()?;
+
+        let ca_key = reader.string()?;
+        Ok(Certificate {
+            key_type,
+            serial,
+            cert_type,
+            key_id,
+            principals,
+            valid_after,
+            valid_before,
+            critical_options,
+            extensions,
+            ca_key_type: Reader::new(ca_key).text()?,
+            ca_fingerprint: fingerprint_blob(ca_key),
+        })
+    }
+}
+
+/// Parse the name and value pairs of critical options or extensions.
+/// Values are themselves encoded as a string when present.
+fn parse_options(data: &[u8]) -> Result<Vec<(String, String)>, SshError> {
+    let mut options = Vec::new();
+    let mut reader = Reader::new(data);
+    while !reader.is_empty() {
+        let name = reader.text()?;
+        let data = reader.string()?;
+        let value = if data.is_empty() {
+            String::new()
+        } else {
+            Reader::new(data).text()?
+        };
+        options.push((name, value));
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fingerprint of the CA that signed the certificates
+    const CA_FINGERPRINT: &str = "SHA256:NvVEdBRRMrMnn2wzjyLIixwNM2Mx0C5Uu3sqYR4AHZk";
+
+    /// `ssh-keygen -s ca -I "deploy key" -n alice,bob -z 42 -V 20240101000000Z:20250101000000Z
+    /// -O force-command=uptime -O source-address=10.0.0.0/8 -O clear -O permit-pty`
+    const USER_CERTIFICATE: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAIKxS/0r4rS+jEIvleR09Zzum8Ilxt9+A/zrsb6aMkAPbAAAAIBjO3KXB02342VFFeRR2lkwzUY0EuvxHgZKtCjB0RU5zAAAAAAAAACoAAAABAAAACmRlcGxveSBrZXkAAAAQAAAABWFsaWNlAAAAA2JvYgAAAABlkgCAAAAAAGd0hYAAAABDAAAADWZvcmNlLWNvbW1hbmQAAAAKAAAABnVwdGltZQAAAA5zb3VyY2UtYWRkcmVzcwAAAA4AAAAKMTAuMC4wLjAvOAAAABIAAAAKcGVybWl0LXB0eQAAAAAAAAAAAAAAMwAAAAtzc2gtZWQyNTUxOQAAACDmfWRP8yhH5ifoNfxYFvhzylZNJnhTOloBNA9SPIcwkwAAAFMAAAALc3NoLWVkMjU1MTkAAABA1AYtig7YnWmnW/vegeMvu2HgYmabz+t1NshdjXSMAgJ9EYwmq36p0CYkMwy3C8lwo3SathrpD1qN7aXGEyCQDg== test";
+
+    /// `ssh-keygen -s ca -h -I web -n web.example.com -z 7 -V always:forever`
+    const HOST_CERTIFICATE: &str = "ecdsa-sha2-nistp256-cert-v01@openssh.com AAAAKGVjZHNhLXNoYTItbmlzdHAyNTYtY2VydC12MDFAb3BlbnNzaC5jb20AAAAgUG9lJiZDVW9KTVrt6Q46cblbtEkr8geZzrwa6VB8M58AAAAIbmlzdHAyNTYAAABBBD7Tx+mE2mDRoe9A/IG0S5CzGHnjraRXxnFAve7kDotGnbVLjN33MTghsGvBgIKioKj99BO6pWNUgAxIJLKdFjUAAAAAAAAABwAAAAIAAAADd2ViAAAAEwAAAA93ZWIuZXhhbXBsZS5jb20AAAAAAAAAAP//////////AAAAAAAAAAAAAAAAAAAAMwAAAAtzc2gtZWQyNTUxOQAAACDmfWRP8yhH5ifoNfxYFvhzylZNJnhTOloBNA9SPIcwkwAAAFMAAAALc3NoLWVkMjU1MTkAAABAjH2AHldaq9eFASg/QEFGYUuhTJz6KWsHnyz9LJpkWHRzJ/8XK+Zt86luAXt1ZbU1KZZQxEeIyNEGb0HY99kgAQ== test";
+
+    /// `ssh-keygen -s ca -I rsa -n root -V 20240101000000Z:20250101000000Z`
+    const RSA_CERTIFICATE: &str = "ssh-rsa-cert-v01@openssh.com AAAAHHNzaC1yc2EtY2VydC12MDFAb3BlbnNzaC5jb20AAAAgO47QkaIoWh2W5HvwRUdD0AJa637Ymb8Ghv+XgXAXpQ0AAAADAQABAAAAgQC8p3xYY68838QDWz1oujDgq5EpI52XeydxqYQlwjSS31+JPcB56TynLT91+YUKk3MlPU7xEjPwYXUlD856OfKtlYDwcShGJgd99kKHe3aXeXBCJreu6FBLn8FiaYFwHb2fWgq8+IVEIMlzMsKfggGjfFkWBYS7wryx5jY6mz7FrwAAAAAAAAAAAAAAAQAAAANyc2EAAAAIAAAABHJvb3QAAAAAZZIAgAAAAABndIWAAAAAAAAAAIIAAAAVcGVybWl0LVgxMS1mb3J3YXJkaW5nAAAAAAAAABdwZXJtaXQtYWdlbnQtZm9yd2FyZGluZwAAAAAAAAAWcGVybWl0LXBvcnQtZm9yd2FyZGluZwAAAAAAAAAKcGVybWl0LXB0eQAAAAAAAAAOcGVybWl0LXVzZXItcmMAAAAAAAAAAAAAADMAAAALc3NoLWVkMjU1MTkAAAAg5n1kT/MoR+Yn6DX8WBb4c8pWTSZ4UzpaATQPUjyHMJMAAABTAAAAC3NzaC1lZDI1NTE5AAAAQPl1DfL6asSozoP4Br4Tesxt+5Y3f1jL8GjnHzm876VCng8opS3LfLw170R77cdpZMFs7113wyZkOjuGwp0ELAA= test";
+
+    #[test]
+    fn parses_user_certificate() {
+        let certificate = Certificate::parse(USER_CERTIFICATE).unwrap();
+        assert_eq!(certificate.key_type, "ssh-ed25519-cert-v01@openssh.com");
+        assert_eq!(certificate.serial, 42);
+        assert_eq!(certificate.cert_type, CertificateType::User);
+        assert_eq!(certificate.key_id, "deploy key");
+        assert_eq!(certificate.principals, ["alice", "bob"]);
+        assert_eq!(certificate.valid_after, 1_704_067_200);
+        assert_eq!(certificate.valid_before, 1_735_689_600);
+        assert_eq!(
+            certificate.critical_options,
+            [
+                (String::from("force-command"), String::from("uptime")),
+                (String::from("source-address"), String::from("10.0.0.0/8")),
+            ]
+        );
+        assert_eq!(
+            certificate.extensions,
+            [(String::from("permit-pty"), String::new())]
+        );
+        assert_eq!(certificate.ca_key_type, "ssh-ed25519");
+        assert_eq!(certificate.ca_fingerprint, CA_FINGERPRINT);
+    }
+
+    #[test]
+    fn parses_host_certificate_valid_forever() {
+        let certificate = Certificate::parse(HOST_CERTIFICATE).unwrap();
+        assert_eq!(
+            certificate.key_type,
+            "ecdsa-sha2-nistp256-cert-v01@openssh.com"
+        );
+        assert_eq!(certificate.serial, 7);
+        assert_eq!(certificate.cert_type, CertificateType::Host);
+        assert_eq!(certificate.principals, ["web.example.com"]);
+        assert_eq!(certificate.valid_after, 0);
+        assert_eq!(certificate.valid_before, FOREVER);
+        assert!(certificate.critical_options.is_empty());
+        assert!(certificate.extensions.is_empty());
+        assert_eq!(certificate.ca_fingerprint, CA_FINGERPRINT);
+    }
+
+    #[test]
+    fn parses_rsa_certificate() {
+        let certificate = Certificate::parse(RSA_CERTIFICATE).unwrap();
+        assert_eq!(certificate.key_type, "ssh-rsa-cert-v01@openssh.com");
+        assert_eq!(certificate.principals, ["root"]);
+        assert_eq!(certificate.extensions.len(), 5);
+        assert_eq!(certificate.ca_fingerprint, CA_FINGERPRINT);
+    }
+
+    #[test]
+    fn rejects_plain_keys() {
+        assert!(matches!(
+            Certificate::parse("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIBjO3KXB02342VFFeRR2lkwzUY0EuvxHgZKtCjB0RU5z test"),
+            Err(SshError::UnsupportedCertificate(key_type)) if key_type == "ssh-ed25519"
+        ));
+    }
+
+    #[test]
+    fn rejects_mismatched_key_type() {
+        let line = USER_CERTIFICATE.replacen("ssh-ed25519", "ssh-rsa", 1);
+        assert!(matches!(
+            Certificate::parse(&line),
+            Err(SshError::MismatchedKeyType(_, _))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_certificates() {
+        let encoded = USER_CERTIFICATE.split_whitespace().nth(1).unwrap();
+        let mut blob = base64::decode(encoded).unwrap();
+        blob.truncate(blob.len() - 100);
+        let line = format!("ssh-ed25519-cert-v01@openssh.com {}", base64::encode(blob));
+
+        assert!(matches!(
+            Certificate::parse(&line),
+            Err(SshError::UnexpectedEnd)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(matches!(
+            Certificate::parse(""),
+            Err(SshError::InvalidFormat)
+        ));
+        assert!(matches!(
+            Certificate::parse("ssh-ed25519-cert-v01@openssh.com !!!"),
+            Err(SshError::InvalidEncoding(_))
+        ));
+    }
+}
@@ -0,0 +1,13 @@
+mod agent;
+mod certificate;
+mod key;
+mod private_key;
+mod wire;
+
+pub use agent::{
+    failure, identities_answer, sign_response, AgentClient, AgentRequest, MAX_MESSAGE_SIZE,
+};
+pub use certificate::{Certificate, CertificateType, FOREVER};
+pub use key::{fingerprint, KeyPair};
+pub use private_key::PrivateKey;
+pub use wire::decode_line;
@@ -0,0 +1,107 @@
+use crate::errors::SshError;
+
+/// Encoder for the data types of the SSH wire format described in RFC 4251
+#[derive(Default)]
+pub struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    /// Append a big-endian 32-bit integer
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Append a length-prefixed string of bytes
+    pub fn string(&mut self, value: &[u8]) -> &mut Self {
+        self.u32(value.len() as u32);
+        self.raw(value)
+    }
+
+    /// Append bytes without a length prefix
+    pub fn raw(&mut self, value: &[u8]) -> &mut Self {
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Decoder for the data types of the SSH wire format described in RFC 4251
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// Read a big-endian 32-bit integer
+    pub fn u32(&mut self) -> Result<u32, SshError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Read a big-endian 64-bit integer
+    pub fn u64(&mut self) -> Result<u64, SshError> {
+        let high = self.u32()? as u64;
+        let low = self.u32()? as u64;
+        Ok(high << 32 | low)
+    }
+
+    /// Read a length-prefixed string of bytes
+    pub fn string(&mut self) -> Result<&'a [u8], SshError> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    /// Read a length-prefixed string that is expected to be UTF-8
+    pub fn text(&mut self) -> Result<String, SshError> {
+        Ok(String::from_utf8_lossy(self.string()?).into_owned())
+    }
+
+    /// Whether all of the data has been read
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SshError> {
+        if self.data.len() < length {
+            return Err(SshError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+/// Decode a line in the `authorized_keys` format into its key type and wire format data
+pub fn decode_line(line: &str) -> Result<(String, Vec<u8>), SshError> {
+    let mut parts = line.split_whitespace();
+    let (kind, encoded) = match (parts.next(), parts.next()) {
+        (Some(kind), Some(encoded)) => (kind, encoded),
+        _ => return Err(SshError::InvalidFormat),
+    };
+    let blob = base64::decode(encoded)?;
+
+    // The type is repeated at the start of the data
+    let encoded_kind = Reader::new(&blob).text()?;
+    if encoded_kind != kind {
+        return Err(SshError::MismatchedKeyType(kind.to_string(), encoded_kind));
+    }
+
+    Ok((kind.to_string(), blob))
+}
@@ -0,0 +1,560 @@
+use crate::errors::SshConfigError;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+/// Includes are followed at most this deep, like OpenSSH
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A host declared in an ssh client configuration with the settings that apply to it
+#[derive(Debug)]
+pub struct HostEntry {
+    pub alias: String,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<String>,
+    pub proxy_jump: Option<String>,
+}
+
+/// The block a directive is declared in
+#[derive(Clone)]
+enum Condition {
+    Global,
+    Host(Vec<String>),
+    /// Match blocks depend on the connection, so they are never applied
+    Match,
+}
+
+struct Directive {
+    condition: Condition,
+    keyword: String,
+    value: String,
+}
+
+#[derive(Default)]
+struct Parsed {
+    /// Every alias without wildcards in the order it was declared
+    aliases: Vec<String>,
+    directives: Vec<Directive>,
+}
+
+/// Read the hosts declared in an ssh client configuration and its includes.
+/// Settings are resolved for every alias without wildcards, where the first value
+/// from a matching block wins like OpenSSH does.
+pub fn read_hosts(path: &Path) -> Result<Vec<HostEntry>, SshConfigError> {
+    let mut ssh_dir = dirs::home_dir().expect("Failed to retrieve user's home directory");
+    ssh_dir.push(".ssh");
+
+    let mut parsed = Parsed::default();
+    read_file(path, &ssh_dir, Condition::Global, 0, &mut parsed)?;
+    resolve(parsed)
+}
+
+/// Resolve the settings of every alias from the directives that apply to it
+fn resolve(parsed: Parsed) -> Result<Vec<HostEntry>, SshConfigError> {
+    let Parsed {
+        aliases,
+        directives,
+    } = parsed;
+
+    aliases
+        .into_iter()
+        .map(|alias| {
+            let value = |keyword: &str| {
+                directives
+                    .iter()
+                    .filter(|directive| directive.keyword == keyword)
+                    .find(|directive| applies(&directive.condition, &alias))
+                    .map(|directive| directive.value.clone())
+            };
+
+            let port = match value("port") {
+                Some(port) => Some(
+                    port.parse()
+                        .map_err(|_| SshConfigError::InvalidPort(alias.clone(), port))?,
+                ),
+                None => None,
+            };
+
+            Ok(HostEntry {
+                hostname: value("hostname").map(|hostname| expand_host(&hostname, &alias)),
+                user: value("user"),
+                port,
+                identity_file: value("identityfile"),
+                proxy_jump: value("proxyjump").filter(|jump| !jump.eq_ignore_ascii_case("none")),
+                alias,
+            })
+        })
+        .collect()
+}
+
+/// Collect the directives from a file, following includes in place
+fn read_file(
+    path: &Path,
+    ssh_dir: &Path,
+    mut condition: Condition,
+    depth: usize,
+    parsed: &mut Parsed,
+) -> Result<(), SshConfigError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(SshConfigError::IncludeDepth(MAX_INCLUDE_DEPTH));
+    }
+
+    let contents = read_to_string(path)
+        .map_err(|e| SshConfigError::ReadError(path.display().to_string(), e))?;
+
+    for (number, line) in contents.lines().enumerate() {
+        let (keyword, arguments) = match split_line(line) {
+            Some(Ok(split)) => split,
+            Some(Err(_)) => {
+                return Err(SshConfigError::UnterminatedQuote(
+                    path.display().to_string(),
+                    number + 1,
+                ))
+            }
+            None => continue,
+        };
+
+        match keyword.as_str() {
+            "host" => {
+                for pattern in &arguments {
+                    if !pattern.contains(&['*', '?', '!'][..]) && !parsed.aliases.contains(pattern)
+                    {
+                        parsed.aliases.push(pattern.clone());
+                    }
+                }
+                condition = Condition::Host(arguments);
+            }
+            "match" => condition = Condition::Match,
+            "include" => {
+                for pattern in arguments {
+                    for included in expand_include(&pattern, ssh_dir) {
+                        read_file(&included, ssh_dir, condition.clone(), depth + 1, parsed)?;
+                    }
+                }
+            }
+            _ => {
+                if let Some(value) = arguments.into_iter().next() {
+                    parsed.directives.push(Directive {
+                        condition: condition.clone(),
+                        keyword,
+                        value,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Split a line into its lowercase keyword and arguments, skipping blank lines and comments.
+/// The keyword may be separated by whitespace or `=`, and arguments may be double quoted.
+fn split_line(line: &str) -> Option<Result<(String, Vec<String>), ()>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let keyword = line[..end].to_lowercase();
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut arguments = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in rest.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    arguments.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            '#' if !quoted && !started => break,
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if quoted {
+        return Some(Err(()));
+    }
+    if started {
+        arguments.push(current);
+    }
+
+    Some(Ok((keyword, arguments)))
+}
+
+/// Whether directives in a block apply to an alias
+fn applies(condition: &Condition, alias: &str) -> bool {
+    match condition {
+        Condition::Global => true,
+        Condition::Host(patterns) => host_matches(patterns, alias),
+        Condition::Match => false,
+    }
+}
+
+/// Whether a host matches a list of patterns, where any negated match excludes it
+pub fn host_matches(patterns: &[String], host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        match pattern.strip_prefix('!') {
+            Some(negated) if glob_matches(&negated.to_lowercase(), &host.to_lowercase()) => {
+                return false
+            }
+            Some(_) => {}
+            None => matched |= glob_matches(&pattern.to_lowercase(), &host.to_lowercase()),
+        }
+    }
+    matched
+}
+
+/// Match text against a pattern where `*` and `?` do not match across directories
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    match pattern.chars().next() {
+        None => text.is_empty(),
+        Some('*') => {
+            glob_matches(&pattern[1..], text)
+                || text
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c != '/' && glob_matches(pattern, &text[c.len_utf8()..]))
+        }
+        Some(p) => text.chars().next().is_some_and(|c| {
+            (p == c || (p == '?' && c != '/'))
+                && glob_matches(&pattern[p.len_utf8()..], &text[c.len_utf8()..])
+        }),
+    }
+}
+
+/// Resolve an include pattern to the files it names, sorted like glob(3).
+/// Relative paths are within `~/.ssh` and patterns are only expanded in the file name.
+pub fn expand_include(pattern: &str, ssh_dir: &Path) -> Vec<PathBuf> {
+    let path = match pattern.strip_prefix("~/") {
+        Some(relative) => ssh_dir.parent().unwrap_or(ssh_dir).join(relative),
+        None => ssh_dir.join(pattern),
+    };
+
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.contains(&['*', '?'][..]) => name.to_string(),
+        _ if path.is_file() => return vec![path],
+        _ => return Vec::new(),
+    };
+    let directory = path.parent().unwrap_or(ssh_dir);
+
+    // Patterns that match nothing are ignored
+    let entries = match read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .and_then(|file| file.to_str())
+                    .is_some_and(|file| !file.starts_with('.') && glob_matches(&name, file))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Substitute the tokens OpenSSH allows in a HostName
+fn expand_host(hostname: &str, alias: &str) -> String {
+    hostname.replace("%h", alias).replace("%%", "%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir, write};
+    use tempfile::TempDir;
+
+    fn split(line: &str) -> (String, Vec<String>) {
+        split_line(line).unwrap().unwrap()
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    /// Write files into a directory standing in for `~/.ssh`
+    fn ssh_dir(files: &[(&str, &str)]) -> TempDir {
+        let directory = tempfile::tempdir().unwrap();
+        for (name, contents) in files {
+            let path = directory.path().join(name);
+            if let Some(parent) = path.parent() {
+                if !parent.exists() {
+                    create_dir(parent).unwrap();
+                }
+            }
+            write(path, contents).unwrap();
+        }
+        directory
+    }
+
+    /// Read the hosts of the `config` file in a directory standing in for `~/.ssh`
+    fn hosts(directory: &TempDir) -> Result<Vec<HostEntry>, SshConfigError> {
+        let mut parsed = Parsed::default();
+        read_file(
+            &directory.path().join("config"),
+            directory.path(),
+            Condition::Global,
+            0,
+            &mut parsed,
+        )?;
+        resolve(parsed)
+    }
+
+    #[test]
+    fn skips_blank_lines_and_comments() {
+        assert!(split_line("").is_none());
+        assert!(split_line("   \t").is_none());
+        assert!(split_line("# Host web").is_none());
+        assert!(split_line("    # indented").is_none());
+    }
+
+    #[test]
+    fn splits_keyword_with_whitespace_or_equals() {
+        let expected = (
+            String::from("hostname"),
+            vec![String::from("web.example.com")],
+        );
+        assert_eq!(split("HostName web.example.com"), expected);
+        assert_eq!(split("HostName=web.example.com"), expected);
+        assert_eq!(split("  HostName = web.example.com  "), expected);
+        assert_eq!(split("HostName\t=\tweb.example.com"), expected);
+    }
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(
+            split(r#"IdentityFile "~/.ssh/my key" other"#).1,
+            ["~/.ssh/my key", "other"]
+        );
+        assert_eq!(split(r#"Host "web" db"#).1, ["web", "db"]);
+        assert_eq!(split(r#"User a"b c"d"#).1, ["ab cd"]);
+        assert_eq!(split(r#"User """#).1, [""]);
+        assert_eq!(
+            split(r#"ProxyCommand "nc # not a comment""#).1,
+            ["nc # not a comment"]
+        );
+    }
+
+    #[test]
+    fn stops_at_trailing_comment() {
+        assert_eq!(split("Port 22 # default").1, ["22"]);
+        assert_eq!(split("User deploy#team").1, ["deploy#team"]);
+    }
+
+    #[test]
+    fn rejects_unterminated_quote() {
+        assert!(matches!(
+            split_line(r#"IdentityFile "~/.ssh/id"#),
+            Some(Err(()))
+        ));
+    }
+
+    #[test]
+    fn matches_host_patterns() {
+        assert!(host_matches(&patterns(&["web"]), "web"));
+        assert!(host_matches(&patterns(&["WEB"]), "web"));
+        assert!(host_matches(
+            &patterns(&["*.example.com"]),
+            "db.example.com"
+        ));
+        assert!(host_matches(&patterns(&["web?"]), "web1"));
+        assert!(!host_matches(&patterns(&["web?"]), "web"));
+        assert!(host_matches(&patterns(&["db", "web"]), "web"));
+        assert!(!host_matches(&patterns(&[]), "web"));
+    }
+
+    #[test]
+    fn negated_patterns_exclude_hosts() {
+        let patterns = patterns(&["*.example.com", "!bastion.example.com"]);
+        assert!(host_matches(&patterns, "web.example.com"));
+        assert!(!host_matches(&patterns, "bastion.example.com"));
+        assert!(!host_matches(&patterns, "BASTION.example.com"));
+
+        // A negated pattern alone never matches
+        assert!(!host_matches(&self::patterns(&["!web"]), "db"));
+    }
+
+    #[test]
+    fn globs_do_not_cross_directories() {
+        assert!(glob_matches("config.d/*", "config.d/vssh"));
+        assert!(!glob_matches("config.d/*", "config.d/nested/vssh"));
+        assert!(!glob_matches("config.d?vssh", "config.d/vssh"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("", "a"));
+    }
+
+    #[test]
+    fn expands_includes() {
+        let directory = ssh_dir(&[
+            ("config.d/b", ""),
+            ("config.d/a", ""),
+            ("config.d/.hidden", ""),
+            ("single", ""),
+        ]);
+        let path = directory.path();
+
+        assert_eq!(
+            expand_include("config.d/*", path),
+            [path.join("config.d/a"), path.join("config.d/b")]
+        );
+        assert_eq!(expand_include("single", path), [path.join("single")]);
+        let absolute = path.join("single").display().to_string();
+        assert_eq!(expand_include(&absolute, path), [path.join("single")]);
+        assert!(expand_include("missing", path).is_empty());
+        assert!(expand_include("missing.d/*", path).is_empty());
+    }
+
+    #[test]
+    fn expands_home_includes() {
+        let home = ssh_dir(&[(".ssh/config", ""), ("shared/hosts", "")]);
+        let path = home.path().join(".ssh");
+        assert_eq!(
+            expand_include("~/shared/hosts", &path),
+            [home.path().join("shared/hosts")]
+        );
+    }
+
+    #[test]
+    fn resolves_first_value() {
+        let directory = ssh_dir(&[(
+            "config",
+            "Host web
+    HostName web.example.com
+    User deploy
+
+Host web db
+    User admin
+    Port 2222
+
+Host *
+    User root
+    IdentityFile ~/.ssh/id_ed25519
+",
+        )]);
+
+        let hosts = hosts(&directory).unwrap();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].alias, "web");
+        assert_eq!(hosts[0].hostname.as_deref(), Some("web.example.com"));
+        assert_eq!(hosts[0].user.as_deref(), Some("deploy"));
+        assert_eq!(hosts[0].port, Some(2222));
+        assert_eq!(hosts[0].identity_file.as_deref(), Some("~/.ssh/id_ed25519"));
+        assert_eq!(hosts[1].alias, "db");
+        assert_eq!(hosts[1].hostname, None);
+        assert_eq!(hosts[1].user.as_deref(), Some("admin"));
+    }
+
+    #[test]
+    fn skips_wildcards_negations_and_match_blocks() {
+        let directory = ssh_dir(&[(
+            "config",
+            "Host *.internal !bastion.internal web
+    ProxyJump bastion
+
+Match host web
+    User matched
+
+Host bastion
+    ProxyJump none
+",
+        )]);
+
+        let hosts = hosts(&directory).unwrap();
+        let aliases = hosts
+            .iter()
+            .map(|host| host.alias.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(aliases, ["web", "bastion"]);
+        assert_eq!(hosts[0].proxy_jump.as_deref(), Some("bastion"));
+        assert_eq!(hosts[0].user, None);
+        assert_eq!(hosts[1].proxy_jump, None);
+    }
+
+    #[test]
+    fn expands_hostname_tokens() {
+        let directory = ssh_dir(&[("config", "Host web\n    HostName %h.example.com\n")]);
+        assert_eq!(
+            hosts(&directory).unwrap()[0].hostname.as_deref(),
+            Some("web.example.com")
+        );
+    }
+
+    #[test]
+    fn follows_includes_in_place() {
+        let directory = ssh_dir(&[
+            (
+                "config",
+                "Include config.d/*\nHost web\n    Include web.conf\n    User fallback\n",
+            ),
+            ("config.d/db", "Host db\n    HostName db.example.com\n"),
+            ("web.conf", "User deploy\n"),
+        ]);
+
+        let hosts = hosts(&directory).unwrap();
+        assert_eq!(hosts[0].alias, "db");
+        assert_eq!(hosts[0].hostname.as_deref(), Some("db.example.com"));
+        assert_eq!(hosts[0].user, None);
+        assert_eq!(hosts[1].alias, "web");
+        assert_eq!(hosts[1].user.as_deref(), Some("deploy"));
+    }
+
+    #[test]
+    fn limits_include_depth() {
+        let directory = ssh_dir(&[("config", "Include config\n")]);
+        assert!(matches!(
+            hosts(&directory),
+            Err(SshConfigError::IncludeDepth(MAX_INCLUDE_DEPTH))
+        ));
+
+        // Exactly as deep as allowed is fine
+        let mut files = (0..MAX_INCLUDE_DEPTH)
+            .map(|level| (format!("{}", level), format!("Include {}\n", level + 1)))
+            .collect::<Vec<_>>();
+        files.push((format!("{}", MAX_INCLUDE_DEPTH), String::from("Host web\n")));
+        files.push((String::from("config"), String::from("Include 1\n")));
+        let files = files
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_str()))
+            .collect::<Vec<_>>();
+        let directory = ssh_dir(&files);
+        assert_eq!(hosts(&directory).unwrap()[0].alias, "web");
+    }
+
+    #[test]
+    fn reports_errors_with_location() {
+        let directory = ssh_dir(&[("config", "Host web\n    User \"deploy\n")]);
+        match hosts(&directory) {
+            Err(SshConfigError::UnterminatedQuote(path, line)) => {
+                assert!(path.ends_with("config"));
+                assert_eq!(line, 2);
+            }
+            _ => panic!("expected an unterminated quote"),
+        }
+
+        let directory = ssh_dir(&[("config", "Host web\n    Port ssh\n")]);
+        assert!(matches!(
+            hosts(&directory),
+            Err(SshConfigError::InvalidPort(alias, port)) if alias == "web" && port == "ssh"
+        ));
+    }
+}
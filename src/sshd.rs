@@ -0,0 +1,159 @@
+/// Add a directive to the contents of an sshd configuration if it is not already present.
+/// Keywords are matched case-insensitively like sshd does. The directive is placed before
+/// the first `Match` block so that it applies globally.
+pub fn add_directive(contents: &str, keyword: &str, value: &str) -> String {
+    let exists = contents.lines().any(|line| {
+        is_keyword(line, keyword)
+            && line
+                .split_whitespace()
+                .skip(1)
+                .collect::<Vec<_>>()
+                .join(" ")
+                == value
+    });
+    if exists {
+        return contents.to_string();
+    }
+
+    insert_global(contents, &format!("{} {}", keyword, value))
+}
+
+/// Insert a line before the first `Match` block, or at the end if there are none
+fn insert_global(contents: &str, directive: &str) -> String {
+    let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+    let position = lines
+        .iter()
+        .position(|line| is_keyword(line, "Match"))
+        .unwrap_or(lines.len());
+    lines.insert(position, directive.to_string());
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    updated
+}
+
+/// Check if a line starts with the given keyword
+fn is_keyword(line: &str, keyword: &str) -> bool {
+    line.split_whitespace()
+        .next()
+        .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+}
+
+/// Get the value of a directive in the global section of an sshd configuration
+pub fn get_directive(contents: &str, keyword: &str) -> Option<String> {
+    contents
+        .lines()
+        .take_while(|line| !is_keyword(line, "Match"))
+        .find(|line| is_keyword(line, keyword))
+        .map(|line| {
+            line.split_whitespace()
+                .skip(1)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+}
+
+/// Set a single-valued directive in the global section of an sshd configuration.
+/// An existing directive is replaced, otherwise it is added before the first `Match` block.
+pub fn set_directive(contents: &str, keyword: &str, value: &str) -> String {
+    let directive = format!("{} {}", keyword, value);
+
+    let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+    let global = lines
+        .iter()
+        .position(|line| is_keyword(line, "Match"))
+        .unwrap_or(lines.len());
+    match lines[..global]
+        .iter()
+        .position(|line| is_keyword(line, keyword))
+    {
+        Some(position) => {
+            lines[position] = directive;
+            let mut updated = lines.join("\n");
+            updated.push('\n');
+            updated
+        }
+        None => insert_global(contents, &directive),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "Port 22
+# TrustedUserCAKeys /etc/ssh/commented.pem
+PasswordAuthentication no
+
+Match User deploy
+    TrustedUserCAKeys /etc/ssh/deploy.pem
+";
+
+    #[test]
+    fn adds_directive_before_match() {
+        let updated = add_directive(CONFIG, "HostCertificate", "/etc/ssh/host-cert.pub");
+        assert_eq!(
+            updated,
+            "Port 22
+# TrustedUserCAKeys /etc/ssh/commented.pem
+PasswordAuthentication no
+
+HostCertificate /etc/ssh/host-cert.pub
+Match User deploy
+    TrustedUserCAKeys /etc/ssh/deploy.pem
+"
+        );
+    }
+
+    #[test]
+    fn adds_directive_once() {
+        let contents = "hostcertificate   /etc/ssh/host-cert.pub\n";
+        assert_eq!(
+            add_directive(contents, "HostCertificate", "/etc/ssh/host-cert.pub"),
+            contents
+        );
+
+        // Other values of repeatable directives are kept
+        assert_eq!(
+            add_directive(contents, "HostCertificate", "/etc/ssh/other-cert.pub"),
+            "hostcertificate   /etc/ssh/host-cert.pub\nHostCertificate /etc/ssh/other-cert.pub\n"
+        );
+    }
+
+    #[test]
+    fn adds_directive_to_empty_config() {
+        assert_eq!(
+            add_directive("", "HostCertificate", "/etc/ssh/host-cert.pub"),
+            "HostCertificate /etc/ssh/host-cert.pub\n"
+        );
+    }
+
+    #[test]
+    fn sets_new_directive() {
+        let updated = set_directive(CONFIG, "TrustedUserCAKeys", "/etc/ssh/ca.pem");
+        assert!(updated.contains("\nTrustedUserCAKeys /etc/ssh/ca.pem\nMatch User deploy\n"));
+        assert!(updated.contains("# TrustedUserCAKeys /etc/ssh/commented.pem\n"));
+        assert!(updated.contains("    TrustedUserCAKeys /etc/ssh/deploy.pem\n"));
+    }
+
+    #[test]
+    fn replaces_global_directive() {
+        let contents = "trustedusercakeys /etc/ssh/old.pem\nMatch all\nTrustedUserCAKeys x\n";
+        assert_eq!(
+            set_directive(contents, "TrustedUserCAKeys", "/etc/ssh/ca.pem"),
+            "TrustedUserCAKeys /etc/ssh/ca.pem\nMatch all\nTrustedUserCAKeys x\n"
+        );
+    }
+
+    #[test]
+    fn gets_global_directive() {
+        assert_eq!(get_directive(CONFIG, "Port").as_deref(), Some("22"));
+        assert_eq!(
+            get_directive("trustedusercakeys  /etc/ssh/a b.pem\n", "TrustedUserCAKeys").as_deref(),
+            Some("/etc/ssh/a b.pem")
+        );
+
+        // Neither comments nor Match blocks are global
+        assert_eq!(get_directive(CONFIG, "TrustedUserCAKeys"), None);
+    }
+}
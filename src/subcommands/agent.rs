@@ -0,0 +1,394 @@
+use crate::api::ApiClient;
+use crate::auth;
+use crate::cache::{CertificateCache, CertificateRequest};
+use crate::config::{Auth, SignOptions};
+use crate::errors::{ApiError, SignError};
+use crate::ssh::{
+    decode_line, failure, identities_answer, sign_response, AgentClient, AgentRequest, Certificate,
+    KeyPair, PrivateKey, MAX_MESSAGE_SIZE,
+};
+use crate::subcommands::cert::summarize;
+use crate::subcommands::connect::{existing_key, EPHEMERAL_TTL};
+use crate::util::{cache_file, fail, now};
+use std::env;
+use std::ffi::OsString;
+use std::fs::{read_to_string, remove_file, set_permissions, Permissions};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Mutex;
+use tokio::task;
+
+/// How signatures are made for the key
+enum Signer {
+    /// The private key is held by vssh
+    Local(Box<KeyPair>),
+    /// The private key is held by the agent that was running when vssh started
+    Upstream(OsString),
+}
+
+/// The state shared between all connections to the agent.
+/// Only the client and certificate change, and they are never locked while waiting on Vault
+/// or another agent.
+struct Agent {
+    client: Mutex<ApiClient>,
+    /// Method to log in again with once the token expires, unless it is given by the environment
+    auth: Option<Option<Auth>>,
+    role: String,
+    sign_options: SignOptions,
+    cache_margin: u64,
+    public_key: String,
+    public_blob: Vec<u8>,
+    comment: String,
+    signer: Signer,
+    ephemeral: bool,
+    /// The most recently signed certificate in the wire format and when it expires
+    certificate: Mutex<Option<(Vec<u8>, u64)>>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn agent(
+    client: ApiClient,
+    auth: Option<Option<Auth>>,
+    role: String,
+    private_key: Option<String>,
+    public_key: Option<String>,
+    ephemeral: bool,
+    socket: Option<String>,
+    mut sign_options: SignOptions,
+    cache_margin: u64,
+) {
+    // Listen in the cache directory by default, which only the current user can access
+    let path = match socket {
+        Some(socket) => PathBuf::from(socket),
+        None => match cache_file("agent.sock") {
+            Ok(path) => path,
+            Err(e) => fail(&format!("Failed to create cache directory: {}", e)),
+        },
+    };
+
+    // Determine the key and how to sign with it
+    let (signer, public_key, comment) = if ephemeral {
+        if sign_options.ttl.is_none() {
+            sign_options.ttl = Some(String::from(EPHEMERAL_TTL));
+        }
+
+        let key = match KeyPair::generate("vssh-ephemeral") {
+            Ok(key) => key,
+            Err(e) => fail(&format!("Failed to generate key pair: {}", e)),
+        };
+        leg::success("Generated ephemeral key pair", None, None);
+
+        let public_key = key.public_key();
+        (
+            Signer::Local(Box::new(key)),
+            public_key,
+            String::from("vssh-ephemeral"),
+        )
+    } else {
+        let (private_path, public_key) = existing_key(private_key, public_key);
+        let comment = private_path.display().to_string();
+        (
+            load_signer(&private_path, &comment, &path),
+            public_key,
+            comment,
+        )
+    };
+
+    let public_blob = match decode_line(&public_key) {
+        Ok((_, blob)) => blob,
+        Err(e) => fail(&format!("Invalid public key: {}", e)),
+    };
+
+    let mut listener = bind(&path);
+
+    let state = Arc::new(Agent {
+        client: Mutex::new(client),
+        auth,
+        role,
+        sign_options,
+        cache_margin,
+        public_key,
+        public_blob,
+        comment,
+        signer,
+        ephemeral,
+        certificate: Mutex::new(None),
+    });
+
+    leg::success(
+        &format!("Agent listening at: {}", path.display()),
+        None,
+        None,
+    );
+    println!("SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;", path.display());
+
+    // Serve clients until interrupted
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(stream, state.clone()));
+                }
+                Err(e) => leg::error(&format!("Failed to accept connection: {}", e), None, None),
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    match remove_file(&path) {
+        Ok(_) => leg::success("Stopped agent", None, None),
+        Err(e) => fail(&format!("Failed to remove agent socket: {}", e)),
+    }
+}
+
+/// Sign locally with unencrypted Ed25519 keys, otherwise rely on the running agent holding the key
+fn load_signer(private_path: &Path, comment: &str, listen: &Path) -> Signer {
+    let local = read_to_string(private_path)
+        .ok()
+        .and_then(|contents| PrivateKey::parse(&contents).ok())
+        .and_then(|key| KeyPair::from_private_key(&key, comment));
+    if let Some(key) = local {
+        return Signer::Local(Box::new(key));
+    }
+
+    match env::var_os("SSH_AUTH_SOCK") {
+        // Forwarding to itself would never get an answer
+        Some(upstream) if resolve(Path::new(&upstream)) == resolve(listen) => fail(
+            "SSH_AUTH_SOCK is the socket this agent listens at, start it where SSH_AUTH_SOCK is the agent holding the key",
+        ),
+        Some(upstream) => {
+            leg::info(
+                "Forwarding signature requests to the running agent, ensure the key is added to it",
+                None,
+                None,
+            );
+            Signer::Upstream(upstream)
+        }
+        None => {
+            fail("Only unencrypted Ed25519 keys can be used without another agent holding the key")
+        }
+    }
+}
+
+/// Resolve the symbolic links in a path to a socket that may not exist yet
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(resolved) = path.canonicalize() {
+        return resolved;
+    }
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Listen at a path, replacing a socket left behind by a previous agent
+fn bind(path: &Path) -> UnixListener {
+    if let Ok(metadata) = path.symlink_metadata() {
+        if !metadata.file_type().is_socket() {
+            fail(&format!("'{}' exists and is not a socket", path.display()));
+        }
+        if let Err(e) = remove_file(path) {
+            fail(&format!("Failed to remove stale socket: {}", e));
+        }
+    }
+
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) => fail(&format!("Failed to listen at '{}': {}", path.display(), e)),
+    };
+    if let Err(e) = set_permissions(path, Permissions::from_mode(0o600)) {
+        fail(&format!("Failed to restrict agent socket: {}", e));
+    }
+
+    listener
+}
+
+/// Answer requests from a client until it disconnects
+async fn serve(mut stream: UnixStream, state: Arc<Agent>) {
+    loop {
+        let mut length = [0u8; 4];
+        if stream.read_exact(&mut length).await.is_err() {
+            return;
+        }
+        let length = u32::from_be_bytes(length);
+        if length > MAX_MESSAGE_SIZE {
+            return;
+        }
+
+        let mut message = vec![0u8; length as usize];
+        if stream.read_exact(&mut message).await.is_err() {
+            return;
+        }
+
+        let reply = match AgentRequest::parse(&message) {
+            Ok(request) => state.respond(request).await,
+            Err(_) => failure(),
+        };
+
+        let mut framed = (reply.len() as u32).to_be_bytes().to_vec();
+        framed.extend_from_slice(&reply);
+        if stream.write_all(&framed).await.is_err() {
+            return;
+        }
+    }
+}
+
+impl Agent {
+    async fn respond(&self, request: AgentRequest) -> Vec<u8> {
+        match request {
+            AgentRequest::Identities => match self.certificate().await {
+                Ok(blob) => identities_answer(&[(blob, self.comment.clone())]),
+                Err(e) => {
+                    leg::error(&e, None, None);
+                    identities_answer(&[])
+                }
+            },
+            AgentRequest::Sign { key, data, flags } => {
+                // Clients may ask with either the certificate or the plain key
+                let known = key == self.public_blob
+                    || self
+                        .certificate
+                        .lock()
+                        .await
+                        .as_ref()
+                        .is_some_and(|(blob, _)| *blob == key);
+                if !known {
+                    return failure();
+                }
+
+                match &self.signer {
+                    Signer::Local(key) => sign_response(&key.sign(&data)),
+                    Signer::Upstream(upstream) => {
+                        // The client for the other agent blocks while waiting for it
+                        let upstream = upstream.clone();
+                        let public_blob = self.public_blob.clone();
+                        let signed = task::spawn_blocking(move || {
+                            AgentClient::connect_to(&upstream)
+                                .and_then(|mut agent| agent.sign(&public_blob, &data, flags))
+                        })
+                        .await;
+
+                        match signed {
+                            Ok(Ok(signature)) => sign_response(&signature),
+                            Ok(Err(e)) => {
+                                leg::error(
+                                    &format!("Upstream agent failed to sign: {}", e),
+                                    None,
+                                    None,
+                                );
+                                failure()
+                            }
+                            Err(e) => {
+                                leg::error(
+                                    &format!("Failed to reach upstream agent: {}", e),
+                                    None,
+                                    None,
+                                );
+                                failure()
+                            }
+                        }
+                    }
+                }
+            }
+            AgentRequest::Unsupported => failure(),
+        }
+    }
+
+    /// Get a certificate that is valid for at least the margin, signing a new one if necessary
+    async fn certificate(&self) -> Result<Vec<u8>, String> {
+        if let Some((blob, valid_before)) = &*self.certificate.lock().await {
+            if *valid_before > now().saturating_add(self.cache_margin) {
+                return Ok(blob.clone());
+            }
+        }
+
+        // Ephemeral keys are never reused, so neither are their certificates
+        let client = self.client.lock().await.clone();
+        let request = if self.ephemeral {
+            None
+        } else {
+            CertificateRequest::new(&client, &self.role, &self.public_key, &self.sign_options).ok()
+        };
+        let mut cache = match CertificateCache::read() {
+            Ok(cache) => cache,
+            Err(e) => {
+                leg::warn(
+                    &format!("Failed to read certificate cache: {}", e),
+                    None,
+                    None,
+                );
+                CertificateCache::default()
+            }
+        };
+
+        let signed = match request
+            .as_ref()
+            .and_then(|request| cache.get(request, self.cache_margin))
+        {
+            Some(cached) => cached.certificate.clone(),
+            None => {
+                let signed = self.sign(client).await.map_err(|e| e.to_string())?;
+                leg::success("Signed public key with role", None, None);
+                summarize(&signed);
+
+                if let Some(request) = request {
+                    if let Ok(certificate) = Certificate::parse(&signed) {
+                        cache.remove_expired();
+                        cache.insert(request, signed.clone(), certificate.valid_before);
+                        if let Err(e) = cache.write() {
+                            leg::warn(
+                                &format!("Failed to write certificate cache: {}", e),
+                                None,
+                                None,
+                            );
+                        }
+                    }
+                }
+                signed
+            }
+        };
+
+        let invalid = |e| format!("Invalid certificate: {}", e);
+        let (_, blob) = decode_line(&signed).map_err(invalid)?;
+        let certificate = Certificate::parse(&signed).map_err(invalid)?;
+        *self.certificate.lock().await = Some((blob.clone(), certificate.valid_before));
+        Ok(blob)
+    }
+
+    /// Sign the public key, logging in again if the token was rejected since it may have
+    /// expired while the agent was running
+    async fn sign(&self, mut client: ApiClient) -> Result<String, SignError> {
+        let signed = client
+            .sign(
+                self.role.clone(),
+                self.public_key.clone(),
+                &self.sign_options,
+            )
+            .await;
+        let auth = match (signed, &self.auth) {
+            (Err(ApiError::PermissionDenied), Some(auth)) => auth,
+            (signed, _) => return signed.map_err(SignError::ApiError),
+        };
+
+        leg::warn("The token was rejected, authenticating again", None, None);
+        auth::authenticate(&mut client, auth.as_ref()).await?;
+        let signed = client
+            .sign(
+                self.role.clone(),
+                self.public_key.clone(),
+                &self.sign_options,
+            )
+            .await
+            .map_err(SignError::ApiError);
+        *self.client.lock().await = client;
+        signed
+    }
+}
@@ -0,0 +1,42 @@
+use crate::api::ApiClient;
+use crate::config::SignOptions;
+use crate::ssh::KeyPair;
+use crate::subcommands::connect::{
+    add_to_agent, existing_key, read_private_key, sign, sign_cached, EPHEMERAL_TTL,
+};
+use crate::util::fail;
+
+pub async fn agent_add(
+    client: &ApiClient,
+    role: String,
+    private_key: Option<String>,
+    public_key: Option<String>,
+    ephemeral: bool,
+    mut sign_options: SignOptions,
+    cache_margin: u64,
+) {
+    // Ephemeral keys are only ever held by the agent
+    if ephemeral {
+        if sign_options.ttl.is_none() {
+            sign_options.ttl = Some(String::from(EPHEMERAL_TTL));
+        }
+
+        let key = match KeyPair::generate("vssh-ephemeral") {
+            Ok(key) => key,
+            Err(e) => fail(&format!("Failed to generate key pair: {}", e)),
+        };
+        leg::success("Generated ephemeral key pair", None, None);
+
+        let signed = sign(client, &role, key.public_key(), &sign_options).await;
+        add_to_agent(&key.private_key(), &signed, "vssh-ephemeral");
+        return;
+    }
+
+    let (private_path, contents) = existing_key(private_key, public_key);
+    let signed = sign_cached(client, &role, contents, &sign_options, cache_margin).await;
+    add_to_agent(
+        &read_private_key(&private_path),
+        &signed,
+        &private_path.display().to_string(),
+    );
+}
@@ -0,0 +1,5 @@
+mod show;
+mod trust;
+
+pub use show::show;
+pub use trust::trust;
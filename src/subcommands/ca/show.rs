@@ -0,0 +1,9 @@
+use crate::api::ApiClient;
+use crate::util::fail;
+
+pub async fn show(client: &ApiClient) {
+    match client.public_key().await {
+        Ok(key) => println!("{}", key),
+        Err(e) => fail(&format!("Failed to get CA public key: {}", e)),
+    }
+}
@@ -0,0 +1,91 @@
+use crate::api::ApiClient;
+use crate::util::fail;
+use std::fs::{create_dir_all, read_to_string, set_permissions, write, Permissions};
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+const MARKER: &str = "@cert-authority";
+
+pub async fn trust(client: &ApiClient, pattern: String, known_hosts: Option<String>) {
+    if pattern.is_empty() || pattern.contains(char::is_whitespace) {
+        fail("The host pattern cannot be empty or contain whitespace");
+    }
+
+    let key = match client.public_key().await {
+        Ok(key) => key,
+        Err(e) => fail(&format!("Failed to get CA public key: {}", e)),
+    };
+    let entry = format!("{} {} {}", MARKER, pattern, key);
+
+    // Default to the user's known hosts file
+    let path = match known_hosts {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let mut path = dirs::home_dir().expect("Failed to retrieve user's home directory");
+            path.push(".ssh");
+            path.push("known_hosts");
+            path
+        }
+    };
+
+    let contents = match read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => fail(&format!("Failed to read '{}': {}", path.display(), e)),
+    };
+
+    // Replace the entry for the same pattern or add a new one
+    let mut found = false;
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        if parts.next() == Some(MARKER) && parts.next() == Some(pattern.as_str()) {
+            if !found {
+                lines.push(entry.clone());
+            }
+            found = true;
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    if !found {
+        lines.push(entry);
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    if updated == contents {
+        leg::info(
+            &format!("The CA is already trusted for '{}'", pattern),
+            None,
+            None,
+        );
+        return;
+    }
+
+    // Create the directory with the same permissions as OpenSSH
+    if let Some(parent) = path.parent() {
+        if !parent.exists() {
+            if let Err(e) = create_dir_all(parent)
+                .and_then(|_| set_permissions(parent, Permissions::from_mode(0o700)))
+            {
+                fail(&format!("Failed to create '{}': {}", parent.display(), e));
+            }
+        }
+    }
+
+    match write(&path, updated) {
+        Ok(_) => leg::success(
+            &format!(
+                "{} the CA for '{}' in: {}",
+                if found { "Updated" } else { "Trusted" },
+                pattern,
+                path.display()
+            ),
+            None,
+            None,
+        ),
+        Err(e) => fail(&format!("Failed to write '{}': {}", path.display(), e)),
+    }
+}
@@ -0,0 +1,24 @@
+use crate::cache::CertificateCache;
+use crate::util::fail;
+
+pub fn clear(expired: bool) {
+    let mut cache = match CertificateCache::read() {
+        Ok(cache) => cache,
+        Err(e) => fail(&format!("Failed to read certificate cache: {}", e)),
+    };
+
+    let removed = if expired {
+        cache.remove_expired()
+    } else {
+        cache.clear()
+    };
+
+    match cache.write() {
+        Ok(_) => leg::success(
+            &format!("Removed {} cached certificate(s)", removed),
+            None,
+            None,
+        ),
+        Err(e) => fail(&format!("Failed to write certificate cache: {}", e)),
+    }
+}
@@ -0,0 +1,43 @@
+use crate::cache::CertificateCache;
+use crate::ssh::FOREVER;
+use crate::util::{fail, format_duration, now};
+
+pub fn list() {
+    let cache = match CertificateCache::read() {
+        Ok(cache) => cache,
+        Err(e) => fail(&format!("Failed to read certificate cache: {}", e)),
+    };
+
+    // Check if any certificates
+    let entries = cache.entries();
+    if entries.is_empty() {
+        leg::success("No cached certificates found", None, None);
+        return;
+    }
+
+    leg::success("Got list of cached certificates", None, None);
+
+    for cached in entries {
+        let request = &cached.request;
+        let expiry = if cached.valid_before == FOREVER {
+            String::from("never expires")
+        } else if cached.is_valid(0) {
+            format!(
+                "expires in {}",
+                format_duration(cached.valid_before - now())
+            )
+        } else {
+            String::from("expired")
+        };
+
+        println!(
+            "{} ({}):\n\tServer: {}\n\tSSH CA Path: {}\n\tPrincipals: {}\n\tKey: {}",
+            request.role,
+            expiry,
+            request.server,
+            request.mount,
+            request.principals.as_deref().unwrap_or("Default"),
+            request.fingerprint
+        );
+    }
+}
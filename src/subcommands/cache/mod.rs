@@ -0,0 +1,5 @@
+mod clear;
+mod list;
+
+pub use clear::clear;
+pub use list::list;
@@ -0,0 +1,118 @@
+use crate::ssh::{Certificate, CertificateType, FOREVER};
+use crate::util::{fail, format_duration, format_timestamp, now};
+use std::fs::read_to_string;
+use std::io::{self, Read};
+
+pub fn inspect(file: String) {
+    // Read from stdin if requested
+    let contents = if file == "-" {
+        let mut contents = String::new();
+        match io::stdin().read_to_string(&mut contents) {
+            Ok(_) => contents,
+            Err(e) => fail(&format!("Failed to read from stdin: {}", e)),
+        }
+    } else {
+        match read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(e) => fail(&format!("Failed to read '{}': {}", file, e)),
+        }
+    };
+
+    let certificate = match Certificate::parse(&contents) {
+        Ok(certificate) => certificate,
+        Err(e) => fail(&format!("Invalid certificate: {}", e)),
+    };
+
+    leg::success("Parsed certificate", None, None);
+
+    let cert_type = match certificate.cert_type {
+        CertificateType::User => String::from("user"),
+        CertificateType::Host => String::from("host"),
+        CertificateType::Unknown(value) => format!("unknown ({})", value),
+    };
+    println!("Type: {} {} certificate", certificate.key_type, cert_type);
+    println!("Serial: {}", certificate.serial);
+    println!("Key ID: {}", certificate.key_id);
+    println!("Principals:{}", format_list(&certificate.principals));
+    println!(
+        "Valid: from {} to {} ({})",
+        format_timestamp(certificate.valid_after),
+        format_timestamp(certificate.valid_before),
+        format_expiry(&certificate)
+    );
+    println!(
+        "Critical Options:{}",
+        format_list(&format_options(&certificate.critical_options))
+    );
+    println!(
+        "Extensions:{}",
+        format_list(&format_options(&certificate.extensions))
+    );
+    println!(
+        "Signing CA: {} ({})",
+        certificate.ca_fingerprint, certificate.ca_key_type
+    );
+}
+
+/// Print the principals and expiry of a freshly signed certificate
+pub fn summarize(signed: &str) {
+    match Certificate::parse(signed) {
+        Ok(certificate) => leg::info(
+            &format!(
+                "Certificate for {} {}",
+                if certificate.principals.is_empty() {
+                    String::from("any principal")
+                } else {
+                    certificate.principals.join(", ")
+                },
+                format_expiry(&certificate)
+            ),
+            None,
+            None,
+        ),
+        Err(e) => leg::warn(&format!("Unable to parse certificate: {}", e), None, None),
+    }
+}
+
+/// Describe when a certificate expires relative to now
+fn format_expiry(certificate: &Certificate) -> String {
+    let now = now();
+    if certificate.valid_before == FOREVER {
+        String::from("never expires")
+    } else if certificate.valid_before <= now {
+        String::from("expired")
+    } else if certificate.valid_after > now {
+        format!(
+            "not valid for another {}",
+            format_duration(certificate.valid_after - now)
+        )
+    } else {
+        format!(
+            "expires in {}",
+            format_duration(certificate.valid_before - now)
+        )
+    }
+}
+
+/// Format option names and values as `name` or `name value`
+fn format_options(options: &[(String, String)]) -> Vec<String> {
+    options
+        .iter()
+        .map(|(name, value)| {
+            if value.is_empty() {
+                name.clone()
+            } else {
+                format!("{} {}", name, value)
+            }
+        })
+        .collect()
+}
+
+/// Format a list with one item per indented line
+fn format_list(items: &[String]) -> String {
+    if items.is_empty() {
+        return String::from(" (none)");
+    }
+
+    items.iter().map(|item| format!("\n\t{}", item)).collect()
+}
@@ -0,0 +1,3 @@
+mod inspect;
+
+pub use inspect::{inspect, summarize};
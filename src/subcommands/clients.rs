@@ -0,0 +1,43 @@
+use crate::api::ApiClient;
+use crate::errors::SignError;
+use std::future::Future;
+
+/// Clients for every SSH CA path and namespace, authenticating only once each is needed
+pub struct Clients<F> {
+    authenticate: F,
+    clients: Vec<(Option<String>, Option<String>, ApiClient)>,
+}
+
+impl<F, Fut> Clients<F>
+where
+    F: Fn(Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<ApiClient, SignError>>,
+{
+    pub fn new(authenticate: F) -> Self {
+        Clients {
+            authenticate,
+            clients: Vec::new(),
+        }
+    }
+
+    /// Get the client for an SSH CA path and namespace, authenticating if there is none yet
+    pub async fn get(
+        &mut self,
+        path: Option<String>,
+        namespace: Option<String>,
+    ) -> Result<&ApiClient, SignError> {
+        let index = match self
+            .clients
+            .iter()
+            .position(|(p, n, _)| *p == path && *n == namespace)
+        {
+            Some(index) => index,
+            None => {
+                let client = (self.authenticate)(path.clone(), namespace.clone()).await?;
+                self.clients.push((path, namespace, client));
+                self.clients.len() - 1
+            }
+        };
+        Ok(&self.clients[index].2)
+    }
+}
@@ -1,56 +1,167 @@
 use crate::api::ApiClient;
-use crate::util::fail;
-use std::fs::{canonicalize, read_to_string};
-use std::io::{ErrorKind, Write};
-use std::process::Command;
+use crate::cache::{CertificateCache, CertificateRequest};
+use crate::config::{Profile, SignOptions};
+use crate::errors::SignError;
+use crate::ssh::{decode_line, AgentClient, Certificate, KeyPair, PrivateKey, FOREVER};
+use crate::subcommands::cert::summarize;
+use crate::subcommands::profiles::JumpHosts;
+use crate::util::{fail, format_duration, now};
+use std::convert::TryFrom;
+use std::fs::{canonicalize, read_to_string, set_permissions, OpenOptions, Permissions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use tempfile::TempDir;
+use tokio::process::Command;
+use tokio::signal::unix::{signal, SignalKind};
 
-pub async fn connect<'a>(
+/// Lifetime requested for certificates of ephemeral keys unless one is set.
+/// The certificate is only needed while authenticating.
+pub const EPHEMERAL_TTL: &str = "5m";
+
+/// Key pair to authenticate with
+pub enum Key {
+    Existing(PathBuf),
+    Ephemeral(Box<KeyPair>),
+}
+
+/// Program used to reach the server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    Ssh,
+    Sftp,
+    Scp,
+    Rsync,
+}
+
+impl Transport {
+    /// Name of the program to run
+    pub fn program(self) -> &'static str {
+        match self {
+            Transport::Ssh => "ssh",
+            Transport::Sftp => "sftp",
+            Transport::Scp => "scp",
+            Transport::Rsync => "rsync",
+        }
+    }
+
+    /// Flag that sets the port, where rsync passes it on to ssh
+    pub fn port_flag(self) -> &'static str {
+        match self {
+            Transport::Ssh | Transport::Rsync => "-p",
+            Transport::Sftp | Transport::Scp => "-P",
+        }
+    }
+
+    /// Build the command with the identity files and ssh options.
+    /// rsync only accepts them as part of the ssh command it runs.
+    pub fn command(self, identities: &[PathBuf], options: &[String]) -> Command {
+        let mut arguments = Vec::new();
+        for identity in identities {
+            arguments.push(String::from("-i"));
+            arguments.push(identity.display().to_string());
+        }
+        arguments.extend(options.iter().cloned());
+
+        let mut command = Command::new(self.program());
+        match self {
+            Transport::Rsync if !arguments.is_empty() => {
+                let ssh = std::iter::once(String::from("ssh"))
+                    .chain(arguments.iter().map(|argument| rsync_quote(argument)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                command.arg("-e").arg(ssh);
+            }
+            Transport::Rsync => {}
+            _ => {
+                command.args(arguments);
+            }
+        }
+        command
+    }
+
+    /// Arguments to connect to a server, where only ssh can run a remote command
+    pub fn destination(self, server: String, remote_command: Vec<String>) -> Vec<String> {
+        if self != Transport::Ssh && !remote_command.is_empty() {
+            fail(&format!(
+                "A remote command cannot be run with {}",
+                self.program()
+            ));
+        }
+
+        let mut arguments = vec![server];
+        arguments.extend(remote_command);
+        arguments
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn connect(
     client: &ApiClient,
     role: String,
     private_key: Option<String>,
     public_key: Option<String>,
-    server: String,
-    sftp: bool,
-    options: String,
-) {
-    // Use provided private key or default
-    let private_key = if let Some(private_key) = private_key {
-        private_key
+    transport: Transport,
+    options: Vec<String>,
+    arguments: Vec<String>,
+    jump_hosts: Option<&JumpHosts<'_>>,
+    ephemeral: bool,
+    agent: bool,
+    mut sign_options: SignOptions,
+    cache_margin: u64,
+) -> Option<i32> {
+    // Generate a key pair for this session or use an existing one
+    let (ephemeral_key, private_path, contents) = if ephemeral {
+        if sign_options.ttl.is_none() {
+            sign_options.ttl = Some(String::from(EPHEMERAL_TTL));
+        }
+
+        let key = match KeyPair::generate("vssh-ephemeral") {
+            Ok(key) => key,
+            Err(e) => fail(&format!("Failed to generate key pair: {}", e)),
+        };
+        leg::success("Generated ephemeral key pair", None, None);
+
+        let contents = key.public_key();
+        (Some(key), None, contents)
     } else {
-        String::from("~/.ssh/id_rsa")
+        let (private_path, contents) = existing_key(private_key, public_key);
+        (None, Some(private_path), contents)
+    };
+    let comment = match &private_path {
+        Some(path) => path.display().to_string(),
+        None => String::from("vssh-ephemeral"),
     };
 
-    // Use provided public key or default
-    let public_key = if let Some(public_key) = public_key {
-        public_key
+    // Ephemeral keys are never reused, so neither are their certificates
+    let signed = if ephemeral_key.is_some() {
+        sign(client, &role, contents, &sign_options).await
     } else {
-        format!("{}.pub", private_key)
+        sign_cached(client, &role, contents, &sign_options, cache_margin).await
     };
 
-    // Ensure private key exists
-    let private_path = ensure_exists(&private_key);
-
-    // Ensure public key exists
-    let public_path = ensure_exists(&public_key);
+    // ssh picks up the certificate from the agent, so no files are needed
+    if agent {
+        let private = match &ephemeral_key {
+            Some(key) => key.private_key(),
+            None => read_private_key(private_path.as_ref().unwrap()),
+        };
+        add_to_agent(&private, &signed, &comment);
 
-    // Read public key to file
-    let contents = match read_to_string(&public_path) {
-        Ok(contents) => contents,
-        Err(e) => fail(&format!(
-            "Failed to read public key '{}': {}",
-            public_path.as_path().to_str().unwrap(),
-            e
-        )),
-    };
+        return run_through(transport, &[], options, &arguments, jump_hosts).await;
+    }
 
-    // Sign the public key
-    let signed = match client.sign(role.to_string(), contents).await {
-        Ok(signed) => signed,
-        Err(e) => fail(&format!("Failed to sign public key: {}", e)),
+    // Only write the ephemeral private key once it is needed
+    let (private_path, key_dir) = match ephemeral_key {
+        Some(key) => {
+            let (private_path, key_dir) = write_key(&key);
+            (private_path, Some(key_dir))
+        }
+        None => (private_path.unwrap(), None),
     };
 
-    leg::success("Signed public key with role", None, None);
-
     // Create output file
     let mut file = match tempfile::NamedTempFile::new() {
         Ok(file) => file,
@@ -72,43 +183,486 @@ pub async fn connect<'a>(
 
     leg::success("Wrote signed public key to temporary file", None, None);
 
-    // Close the temporary file, but don't remove it to be passed to ssh/sftp
+    // Close the temporary file, but don't remove it to be passed to the transport
     let file_path = file.into_temp_path();
 
-    // Check whether to run SSH or SFTP
-    let command = if sftp { "sftp" } else { "ssh" };
-
-    // Run command
-    let mut child = match Command::new(command)
-        .arg("-i")
-        .arg(private_path)
-        .arg("-i")
-        .arg(&file_path)
-        .arg(server)
-        .args(options.split_whitespace().collect::<Vec<&str>>())
+    let identities = [private_path, file_path.to_path_buf()];
+    let status = run_through(transport, &identities, options, &arguments, jump_hosts).await;
+
+    // Clean up even if the transport failed so that the exit status is still propagated
+    match file_path.close() {
+        Ok(_) => leg::success("Cleaned up signed public key", None, None),
+        Err(e) => match e.kind() {
+            ErrorKind::PermissionDenied => leg::error(
+                "Cannot remove signed certificate: permission denied",
+                None,
+                None,
+            ),
+            _ => leg::error(
+                &format!("Failed to remove signed certificate: {}", e),
+                None,
+                None,
+            ),
+        },
+    };
+
+    // Remove the ephemeral key pair
+    if let Some(key_dir) = key_dir {
+        match key_dir.close() {
+            Ok(_) => leg::success("Cleaned up ephemeral key pair", None, None),
+            Err(e) => leg::error(
+                &format!("Failed to remove ephemeral key pair: {}", e),
+                None,
+                None,
+            ),
+        }
+    }
+
+    status
+}
+
+/// Exit with the status of the transport, or 1 if it could not be run
+pub fn exit_with(status: Option<i32>) {
+    match status {
+        Some(0) => {}
+        Some(code) => exit(code),
+        None => exit(1),
+    }
+}
+
+/// Run the transport through the jump hosts, if there are any. Their certificates are only
+/// written now that nothing else can fail, and are removed again once the transport exits.
+async fn run_through(
+    transport: Transport,
+    identities: &[PathBuf],
+    mut options: Vec<String>,
+    arguments: &[String],
+    jump_hosts: Option<&JumpHosts<'_>>,
+) -> Option<i32> {
+    let chain = match jump_hosts {
+        Some(jump_hosts) => match jump_hosts.write() {
+            Ok(chain) => {
+                // Jump hosts take precedence over any other ProxyCommand
+                let mut chain_options = chain.options(jump_hosts.target());
+                chain_options.append(&mut options);
+                options = chain_options;
+                Some(chain)
+            }
+            Err(e) => {
+                leg::error(
+                    &format!("Failed to write jump host certificates: {}", e),
+                    None,
+                    None,
+                );
+                return None;
+            }
+        },
+        None => None,
+    };
+
+    let status = run(transport, identities, &options, arguments).await;
+    if let Some(chain) = chain {
+        chain.close();
+    }
+    status
+}
+
+/// Run the transport with the given identity files and wait for it to exit, forwarding
+/// SIGINT, SIGTERM, SIGHUP and SIGWINCH to it. Returns the exit status, where death by a signal
+/// is 128 plus the signal like shells report, or None if the command could not be run.
+async fn run(
+    transport: Transport,
+    identities: &[PathBuf],
+    options: &[String],
+    arguments: &[String],
+) -> Option<i32> {
+    // Handling the signals also keeps them from stopping vssh before it cleans up,
+    // which must start before the child so none arrive in between
+    let mut interrupt = signal(SignalKind::interrupt()).expect("Failed to listen for signals");
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for signals");
+    let mut hangup = signal(SignalKind::hangup()).expect("Failed to listen for signals");
+    let mut window_change =
+        signal(SignalKind::window_change()).expect("Failed to listen for signals");
+
+    // Options must come before the destination for ssh to use them
+    let mut child = match transport
+        .command(identities, options)
+        .args(arguments)
         .spawn()
     {
         Ok(child) => child,
-        Err(e) => fail(&format!("Failed to start ssh command: {}", e)),
+        Err(e) => {
+            leg::error(
+                &format!("Failed to start {} command: {}", transport.program(), e),
+                None,
+                None,
+            );
+            return None;
+        }
     };
 
-    leg::wait("Running SSH command...", None, None);
+    leg::wait(
+        &format!("Running {} command...", transport.program()),
+        None,
+        None,
+    );
 
-    // Bring to foreground
-    child.wait().expect("Failed to wait on child");
+    let pid = child.id() as libc::pid_t;
 
-    // Remove signed certificate
-    match file_path.close() {
-        Ok(_) => leg::success("Cleaned up signed public key", None, None),
+    let status = loop {
+        let forwarded = tokio::select! {
+            status = &mut child => break status,
+            _ = interrupt.recv() => libc::SIGINT,
+            _ = terminate.recv() => libc::SIGTERM,
+            _ = hangup.recv() => libc::SIGHUP,
+            _ = window_change.recv() => libc::SIGWINCH,
+        };
+
+        // The child may have exited since, which only makes this fail
+        unsafe {
+            libc::kill(pid, forwarded);
+        }
+    };
+
+    // Reported instead of exiting so that the certificates are still cleaned up
+    match status {
+        Ok(status) => Some(
+            status
+                .code()
+                .or_else(|| status.signal().map(|signal| 128 + signal))
+                .unwrap_or(1),
+        ),
         Err(e) => {
-            match e.kind() {
-                ErrorKind::PermissionDenied => {
-                    fail("Cannot remove signed certificate: permission denied")
-                }
-                _ => fail(&format!("Failed to remove signed certificate: {}", e)),
-            };
+            leg::error(
+                &format!("Failed to wait on {}: {}", transport.program(), e),
+                None,
+                None,
+            );
+            None
         }
+    }
+}
+
+/// Quote an argument of the remote shell command for rsync, which only splits it on
+/// spaces and treats a doubled quote within quotes as a literal one
+fn rsync_quote(argument: &str) -> String {
+    if !argument.is_empty() && !argument.contains(&[' ', '\t', '\'', '"'][..]) {
+        return argument.to_string();
+    }
+    format!("'{}'", argument.replace('\'', "''"))
+}
+
+/// Split ssh options into arguments using shell quoting rules
+pub fn parse_options(options: &str) -> Vec<String> {
+    match shell_words::split(options) {
+        Ok(options) => options,
+        Err(e) => fail(&format!("Invalid ssh options '{}': {}", options, e)),
+    }
+}
+
+/// Read a private key file
+pub fn read_private_key(path: &Path) -> String {
+    match read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => fail(&format!(
+            "Failed to read private key '{}': {}",
+            path.display(),
+            e
+        )),
+    }
+}
+
+/// Add a private key and its certificate to the agent at `SSH_AUTH_SOCK`.
+/// The agent removes them when the certificate expires.
+pub fn add_to_agent(private_key: &str, signed: &str, comment: &str) {
+    let key = match PrivateKey::parse(private_key) {
+        Ok(key) => key,
+        Err(e) => fail(&format!("Failed to read private key: {}", e)),
+    };
+    let (blob, certificate) = match decode_line(signed).and_then(|(_, blob)| {
+        let certificate = Certificate::parse(signed)?;
+        Ok((blob, certificate))
+    }) {
+        Ok(parsed) => parsed,
+        Err(e) => fail(&format!("Invalid certificate: {}", e)),
     };
+
+    // Lifetimes are limited to 32 bits, longer ones are close enough to forever
+    let lifetime = if certificate.valid_before == FOREVER {
+        None
+    } else if certificate.valid_before <= now() {
+        fail("Certificate has already expired")
+    } else {
+        Some(u32::try_from(certificate.valid_before - now()).unwrap_or(u32::MAX))
+    };
+
+    let added = AgentClient::connect()
+        .and_then(|mut agent| agent.add_certificate(&key, &blob, comment, lifetime));
+    match added {
+        Ok(_) => leg::success(
+            &match lifetime {
+                Some(lifetime) => format!(
+                    "Added certificate to the agent for {}",
+                    format_duration(lifetime as u64)
+                ),
+                None => String::from("Added certificate to the agent"),
+            },
+            None,
+            None,
+        ),
+        Err(e) => fail(&format!("Failed to add certificate to the agent: {}", e)),
+    }
+}
+
+/// Sign the public key with the role
+pub async fn sign(
+    client: &ApiClient,
+    role: &str,
+    contents: String,
+    sign_options: &SignOptions,
+) -> String {
+    match try_sign(client, role, contents, sign_options).await {
+        Ok(signed) => signed,
+        Err(e) => fail(&e.to_string()),
+    }
+}
+
+/// Sign the public key with the role, returning errors instead of exiting
+async fn try_sign(
+    client: &ApiClient,
+    role: &str,
+    contents: String,
+    sign_options: &SignOptions,
+) -> Result<String, SignError> {
+    let signed = client
+        .sign(role.to_string(), contents, sign_options)
+        .await
+        .map_err(SignError::ApiError)?;
+
+    leg::success("Signed public key with role", None, None);
+    summarize(&signed);
+    Ok(signed)
+}
+
+/// Reuse a certificate previously signed for the same request if it is still valid,
+/// otherwise sign the public key and cache the certificate.
+/// Problems with the cache only produce warnings.
+pub async fn sign_cached(
+    client: &ApiClient,
+    role: &str,
+    contents: String,
+    sign_options: &SignOptions,
+    margin: u64,
+) -> String {
+    match try_sign_cached(client, role, contents, sign_options, margin).await {
+        Ok(signed) => signed,
+        Err(e) => fail(&e.to_string()),
+    }
+}
+
+/// Sign or reuse a certificate like `sign_cached`, returning errors instead of exiting
+async fn try_sign_cached(
+    client: &ApiClient,
+    role: &str,
+    contents: String,
+    sign_options: &SignOptions,
+    margin: u64,
+) -> Result<String, SignError> {
+    let request = match CertificateRequest::new(client, role, &contents, sign_options) {
+        Ok(request) => request,
+        Err(e) => {
+            leg::warn(&format!("Not caching certificate: {}", e), None, None);
+            return try_sign(client, role, contents, sign_options).await;
+        }
+    };
+
+    let mut cache = match CertificateCache::read() {
+        Ok(cache) => cache,
+        Err(e) => {
+            leg::warn(
+                &format!("Failed to read certificate cache: {}", e),
+                None,
+                None,
+            );
+            CertificateCache::default()
+        }
+    };
+
+    if let Some(cached) = cache.get(&request, margin) {
+        leg::success("Reusing cached certificate", None, None);
+        summarize(&cached.certificate);
+        return Ok(cached.certificate.clone());
+    }
+
+    let signed = try_sign(client, role, contents, sign_options).await?;
+    match Certificate::parse(&signed) {
+        Ok(certificate) => {
+            cache.remove_expired();
+            cache.insert(request, signed.clone(), certificate.valid_before);
+            if let Err(e) = cache.write() {
+                leg::warn(
+                    &format!("Failed to write certificate cache: {}", e),
+                    None,
+                    None,
+                );
+            }
+        }
+        Err(e) => leg::warn(&format!("Not caching certificate: {}", e), None, None),
+    }
+
+    Ok(signed)
+}
+
+/// Sign the key of a profile with its role and signing parameters,
+/// generating a key pair if the profile uses ephemeral keys
+pub async fn sign_profile(
+    client: &ApiClient,
+    profile: &Profile,
+    cache_margin: u64,
+) -> Result<(Key, String), SignError> {
+    sign_profile_key(client, profile, profile_key(profile), cache_margin).await
+}
+
+/// Read the existing key pair of a profile, or nothing if it uses ephemeral keys
+pub fn profile_key(profile: &Profile) -> Option<(PathBuf, String)> {
+    if profile.ephemeral {
+        None
+    } else {
+        Some(existing_key(
+            profile.private_key.clone(),
+            profile.public_key.clone(),
+        ))
+    }
+}
+
+/// Sign a key of a profile that was already read with `profile_key`,
+/// generating a key pair if the profile uses ephemeral keys
+pub async fn sign_profile_key(
+    client: &ApiClient,
+    profile: &Profile,
+    key: Option<(PathBuf, String)>,
+    cache_margin: u64,
+) -> Result<(Key, String), SignError> {
+    if let Some((private_path, contents)) = key {
+        let certificate = try_sign_cached(
+            client,
+            &profile.role,
+            contents,
+            &profile.sign_options,
+            cache_margin,
+        )
+        .await?;
+        Ok((Key::Existing(private_path), certificate))
+    } else {
+        let mut sign_options = profile.sign_options.clone();
+        if sign_options.ttl.is_none() {
+            sign_options.ttl = Some(String::from(EPHEMERAL_TTL));
+        }
+
+        let key = match KeyPair::generate("vssh-ephemeral") {
+            Ok(key) => key,
+            Err(e) => fail(&format!("Failed to generate key pair: {}", e)),
+        };
+        let certificate = try_sign(client, &profile.role, key.public_key(), &sign_options).await?;
+        Ok((Key::Ephemeral(Box::new(key)), certificate))
+    }
+}
+
+/// Read the public key of an existing key pair, defaulting to `~/.ssh/id_rsa`
+pub fn existing_key(private_key: Option<String>, public_key: Option<String>) -> (PathBuf, String) {
+    // Use provided private key or default
+    let private_key = if let Some(private_key) = private_key {
+        private_key
+    } else {
+        String::from("~/.ssh/id_rsa")
+    };
+
+    // Use provided public key or default
+    let public_key = if let Some(public_key) = public_key {
+        public_key
+    } else {
+        format!("{}.pub", private_key)
+    };
+
+    // Ensure private key exists
+    let private_path = ensure_exists(&private_key);
+
+    // Ensure public key exists
+    let public_path = ensure_exists(&public_key);
+
+    // Read public key to file
+    let contents = match read_to_string(&public_path) {
+        Ok(contents) => contents,
+        Err(e) => fail(&format!(
+            "Failed to read public key '{}': {}",
+            public_path.as_path().to_str().unwrap(),
+            e
+        )),
+    };
+
+    (private_path, contents)
+}
+
+/// Write a private key to a temporary directory only accessible by the current user.
+/// Returns the path to the private key and the directory to remove afterwards.
+fn write_key(key: &KeyPair) -> (PathBuf, TempDir) {
+    let directory = private_dir();
+    let private_path = directory.path().join("id_ed25519");
+
+    if let Err(e) = write_private(&private_path, &key.private_key()) {
+        drop(directory);
+        fail(&format!("Failed to write ephemeral private key: {}", e));
+    }
+
+    (private_path, directory)
+}
+
+/// Create a temporary directory only accessible by the current user, exiting on failure
+pub fn private_dir() -> TempDir {
+    match create_private_dir() {
+        Ok(directory) => directory,
+        Err(e) => fail(&format!("Failed to create temporary directory: {}", e)),
+    }
+}
+
+/// Create a temporary directory only accessible by the current user, removing it again
+/// if it cannot be restricted
+pub fn create_private_dir() -> io::Result<TempDir> {
+    let directory = tempfile::Builder::new().prefix("vssh-").tempdir()?;
+    set_permissions(directory.path(), Permissions::from_mode(0o700))?;
+    Ok(directory)
+}
+
+/// Write a new file only readable by the current user
+pub fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+}
+
+/// Write a certificate and, for ephemeral keys, the private key to a private directory.
+/// Returns the private key and certificate to pass to ssh.
+pub fn write_identity(
+    directory: &Path,
+    name: &str,
+    key: &Key,
+    certificate: &str,
+) -> io::Result<[PathBuf; 2]> {
+    let certificate_path = directory.join(format!("{}-cert.pub", name));
+    write_private(&certificate_path, certificate)?;
+
+    let private_path = match key {
+        Key::Existing(path) => path.clone(),
+        Key::Ephemeral(key) => {
+            let path = directory.join(name);
+            write_private(&path, &key.private_key())?;
+            path
+        }
+    };
+    Ok([private_path, certificate_path])
 }
 
 /// Ensure a file exists and also convert it to an absolute path if it was not
@@ -126,3 +680,69 @@ fn ensure_exists(path: &str) -> std::path::PathBuf {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_empty_options() {
+        assert!(parse_options("").is_empty());
+        assert!(parse_options("  \t ").is_empty());
+    }
+
+    #[test]
+    fn parses_options_with_equals() {
+        assert_eq!(
+            parse_options("-o StrictHostKeyChecking=no -oUser=deploy"),
+            ["-o", "StrictHostKeyChecking=no", "-oUser=deploy"]
+        );
+        assert_eq!(parse_options("-o Port = 2222"), ["-o", "Port", "=", "2222"]);
+    }
+
+    #[test]
+    fn parses_quoted_options() {
+        assert_eq!(
+            parse_options(r#"-o "ProxyCommand=nc %h %p" -v"#),
+            ["-o", "ProxyCommand=nc %h %p", "-v"]
+        );
+        assert_eq!(
+            parse_options("-o 'ProxyCommand=ssh -W \"%h:%p\" bastion'"),
+            ["-o", r#"ProxyCommand=ssh -W "%h:%p" bastion"#]
+        );
+        assert_eq!(
+            parse_options(r#"-o "IdentityAgent=~/my agent.sock""#),
+            ["-o", "IdentityAgent=~/my agent.sock"]
+        );
+        assert_eq!(
+            parse_options(r"-o SetEnv=NAME=a\ b"),
+            ["-o", "SetEnv=NAME=a b"]
+        );
+        assert_eq!(parse_options(r#"-o User="""#), ["-o", "User="]);
+    }
+
+    #[test]
+    fn places_remote_command_after_server() {
+        assert_eq!(
+            Transport::Ssh.destination(
+                String::from("deploy@web"),
+                vec![String::from("ls"), String::from("-la")]
+            ),
+            ["deploy@web", "ls", "-la"]
+        );
+        assert_eq!(
+            Transport::Sftp.destination(String::from("deploy@web"), Vec::new()),
+            ["deploy@web"]
+        );
+    }
+
+    #[test]
+    fn quotes_rsync_arguments() {
+        assert_eq!(rsync_quote("-i"), "-i");
+        assert_eq!(rsync_quote("ProxyCommand=nc"), "ProxyCommand=nc");
+        assert_eq!(rsync_quote(""), "''");
+        assert_eq!(rsync_quote("/tmp/my key"), "'/tmp/my key'");
+        assert_eq!(rsync_quote("it's"), "'it''s'");
+        assert_eq!(rsync_quote(r#"say "hi""#), r#"'say "hi"'"#);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::api::ApiClient;
+use crate::cache::DEFAULT_CACHE_MARGIN;
+use crate::cli::Transfer;
+use crate::config::{Config, Profile, SignOptions};
+use crate::errors::SignError;
+use crate::subcommands::clients::Clients;
+use crate::subcommands::connect::{connect, exit_with, parse_options, Transport};
+use crate::subcommands::profiles::{self, profile_options};
+use crate::util::fail;
+use std::future::Future;
+
+pub async fn copy<F, Fut>(
+    config: &Config,
+    transport: Transport,
+    transfer: Transfer,
+    path: Option<String>,
+    namespace: Option<String>,
+    authenticate: F,
+) where
+    F: Fn(Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<ApiClient, SignError>>,
+{
+    // The target is either a profile or the role to sign with
+    let selected = config.profiles.get_key_value(&transfer.target);
+    let profile = selected.map(|(_, profile)| profile);
+    if profile.is_some() {
+        leg::success("Retrieved selected profile", None, None);
+    }
+
+    // Replace `profile:path` with the profile's address
+    let mut referenced = None;
+    let paths = transfer
+        .paths
+        .into_iter()
+        .map(|path| match resolve(config, &path) {
+            Some((remote, name, profile)) => {
+                referenced = referenced.or(Some((name, profile)));
+                remote
+            }
+            None => path,
+        })
+        .collect::<Vec<_>>();
+
+    // Connection options come from the target profile, or otherwise the first one in the paths
+    let cache_margin = config.cache_margin.unwrap_or(DEFAULT_CACHE_MARGIN);
+    let mut clients = Clients::new(authenticate);
+    let connection = selected
+        .map(|(name, profile)| (name.as_str(), profile))
+        .or(referenced);
+    let mut options = Vec::new();
+    let mut jump_hosts = None;
+    if let Some((name, profile)) = connection {
+        options.extend(profile_options(profile, transport));
+        jump_hosts =
+            match profiles::jump_hosts(config, name, profile, &mut clients, cache_margin).await {
+                Ok(jump_hosts) => jump_hosts,
+                Err(e) => fail(&e.to_string()),
+            };
+    }
+    options.extend(parse_options(&transfer.options.unwrap_or_default()));
+
+    let mut arguments = transfer.arguments;
+    arguments.extend(paths);
+
+    let path = path.or_else(|| profile.and_then(|profile| profile.path.clone()));
+    let namespace = namespace.or_else(|| profile.and_then(|profile| profile.namespace.clone()));
+    let client = match clients.get(path, namespace).await {
+        Ok(client) => client,
+        Err(e) => fail(&e.to_string()),
+    };
+
+    // Command line overrides take precedence over the profile's settings
+    let sign_options = SignOptions::from(transfer.signing);
+    let (role, private_key, public_key, ephemeral, sign_options) = match profile {
+        Some(profile) => {
+            let keys_given = transfer.private_key.is_some() || transfer.public_key.is_some();
+            (
+                profile.role.clone(),
+                transfer.private_key.or_else(|| profile.private_key.clone()),
+                transfer.public_key.or_else(|| profile.public_key.clone()),
+                transfer.ephemeral || (profile.ephemeral && !keys_given),
+                sign_options.or(&profile.sign_options),
+            )
+        }
+        None => (
+            transfer.target,
+            transfer.private_key,
+            transfer.public_key,
+            transfer.ephemeral,
+            sign_options,
+        ),
+    };
+
+    let status = connect(
+        client,
+        role,
+        private_key,
+        public_key,
+        transport,
+        options,
+        arguments,
+        jump_hosts.as_ref(),
+        ephemeral,
+        transfer.agent,
+        sign_options,
+        cache_margin,
+    )
+    .await;
+    exit_with(status);
+}
+
+/// Resolve `profile:path` to `user@address:path` if it names a profile
+fn resolve<'a>(config: &'a Config, path: &str) -> Option<(String, &'a str, &'a Profile)> {
+    let (name, remote) = path.split_once(':')?;
+    if name.contains('/') {
+        return None;
+    }
+
+    let (name, profile) = config.profiles.get_key_value(name)?;
+    Some((
+        format!("{}@{}:{}", profile.username, profile.address, remote),
+        name.as_str(),
+        profile,
+    ))
+}
@@ -0,0 +1,414 @@
+use crate::api::ApiClient;
+use crate::config::{Config, Profile};
+use crate::errors::SignError;
+use crate::ssh_config::host_matches;
+use crate::subcommands::clients::Clients;
+use crate::subcommands::connect::{
+    private_dir, profile_key, sign_profile_key, write_identity, Key, Transport,
+};
+use crate::subcommands::profiles::{check_proxy_jump, jump_hosts, profile_options, JumpHosts};
+use crate::util::fail;
+use std::future::{pending, Future};
+use std::io::{self, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{exit, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::{watch, Semaphore};
+
+/// A profile to run the command on
+struct Target<'a> {
+    name: &'a str,
+    profile: &'a Profile,
+    /// Index of the key and certificate to authenticate with
+    credentials: usize,
+}
+
+/// A key and the certificate signed for it
+struct Credentials {
+    /// Identifies the client, role, key and parameters the certificate was signed for
+    request: String,
+    key: Key,
+    certificate: String,
+}
+
+/// How the command ended on a profile
+enum Outcome {
+    Exited(i32),
+    Failed(String),
+    Cancelled,
+    Skipped,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn exec<F, Fut>(
+    config: &Config,
+    patterns: String,
+    path: Option<String>,
+    namespace: Option<String>,
+    remote_command: Vec<String>,
+    parallel: usize,
+    fail_fast: bool,
+    cache_margin: u64,
+    authenticate: F,
+) where
+    F: Fn(Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<ApiClient, SignError>>,
+{
+    if parallel == 0 {
+        fail("At least one command must be allowed to run in parallel");
+    }
+
+    // Profiles whose names match any of the comma separated patterns
+    let patterns = patterns.split(',').map(String::from).collect::<Vec<_>>();
+    let mut selected = config
+        .profiles
+        .iter()
+        .filter(|(name, _)| host_matches(&patterns, name))
+        .collect::<Vec<_>>();
+    selected.sort_by_key(|(name, _)| name.as_str());
+    if selected.is_empty() {
+        fail(&format!("No profiles match '{}'", patterns.join(",")));
+    }
+    leg::info(
+        &format!("Running on {} profiles", selected.len()),
+        None,
+        None,
+    );
+
+    // Profiles with the same SSH CA path and namespace share a client
+    let mut clients = Clients::new(authenticate);
+    let mut credentials: Vec<Credentials> = Vec::new();
+    let mut targets = Vec::new();
+    for (name, profile) in selected {
+        let path = path.clone().or_else(|| profile.path.clone());
+        let namespace = namespace.clone().or_else(|| profile.namespace.clone());
+        let client = match clients.get(path, namespace).await {
+            Ok(client) => client,
+            Err(e) => fail(&e.to_string()),
+        };
+
+        // Sign once for every distinct role, key and set of parameters
+        let key = profile_key(profile);
+        let request = format!(
+            "{}\0{}\0{}\0{}\0{}",
+            client.token_key(),
+            client.path(),
+            profile.role,
+            key.as_ref().map_or_else(
+                || String::from("ephemeral"),
+                |(private_path, _)| private_path.display().to_string()
+            ),
+            serde_json::to_value(&profile.sign_options)
+                .map(|options| options.to_string())
+                .unwrap_or_default()
+        );
+
+        let index = match credentials
+            .iter()
+            .position(|credentials| credentials.request == request)
+        {
+            Some(index) => index,
+            None => {
+                let (key, certificate) =
+                    match sign_profile_key(client, profile, key, cache_margin).await {
+                        Ok(signed) => signed,
+                        Err(e) => fail(&e.to_string()),
+                    };
+                credentials.push(Credentials {
+                    request,
+                    key,
+                    certificate,
+                });
+                credentials.len() - 1
+            }
+        };
+
+        targets.push(Target {
+            name,
+            profile,
+            credentials: index,
+        });
+    }
+
+    // Jump hosts authenticate with their own certificates, signed once for every
+    // distinct list of jump hosts
+    let mut signed_hops: Vec<(&[String], JumpHosts)> = Vec::new();
+    let mut target_chains = Vec::new();
+    for target in &targets {
+        let jump = target.profile.jump.as_slice();
+        let index = match signed_hops.iter().position(|(hops, _)| *hops == jump) {
+            Some(index) => {
+                check_proxy_jump(target.name, target.profile);
+                Some(index)
+            }
+            None => {
+                let hosts = match jump_hosts(
+                    config,
+                    target.name,
+                    target.profile,
+                    &mut clients,
+                    cache_margin,
+                )
+                .await
+                {
+                    Ok(hosts) => hosts,
+                    Err(e) => fail(&e.to_string()),
+                };
+                hosts.map(|hosts| {
+                    signed_hops.push((jump, hosts));
+                    signed_hops.len() - 1
+                })
+            }
+        };
+        target_chains.push(index);
+    }
+
+    // Write the certificates and ephemeral keys only once everything is signed
+    let directory = private_dir();
+    let mut identities = Vec::new();
+    for (index, credentials) in credentials.iter().enumerate() {
+        match write_identity(
+            directory.path(),
+            &index.to_string(),
+            &credentials.key,
+            &credentials.certificate,
+        ) {
+            Ok(identity) => identities.push(identity),
+            Err(e) => {
+                drop(directory);
+                fail(&format!("Failed to write certificate: {}", e));
+            }
+        }
+    }
+    let mut chains = Vec::new();
+    for (_, hosts) in &signed_hops {
+        match hosts.write() {
+            Ok(chain) => chains.push(chain),
+            Err(e) => {
+                drop(chains);
+                drop(directory);
+                fail(&format!("Failed to write jump host certificates: {}", e));
+            }
+        }
+    }
+
+    // Stop starting commands once interrupted or, if failing fast, once one fails
+    let (cancel, cancelled) = watch::channel(false);
+    let cancel = Arc::new(cancel);
+    let interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = interrupt.broadcast(true);
+        }
+    });
+
+    let width = targets
+        .iter()
+        .map(|target| target.name.len())
+        .max()
+        .unwrap_or_default()
+        .max("PROFILE".len());
+    let semaphore = Arc::new(Semaphore::new(parallel));
+
+    let mut handles = Vec::new();
+    for (target, chain) in targets.iter().zip(&target_chains) {
+        let mut options = vec![String::from("-o"), String::from("BatchMode=yes")];
+        options.extend(profile_options(target.profile, Transport::Ssh));
+        if let Some(index) = chain {
+            options.extend(chains[*index].options(target.profile));
+        }
+
+        let mut command = Transport::Ssh.command(&identities[target.credentials], &options);
+        command
+            .arg(format!(
+                "{}@{}",
+                target.profile.username, target.profile.address
+            ))
+            .args(&remote_command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let prefix = format!("{:width$} | ", target.name, width = width);
+        let semaphore = semaphore.clone();
+        let cancel = cancel.clone();
+        let cancelled = cancelled.clone();
+        handles.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire().await;
+            if *cancelled.borrow() {
+                return (Outcome::Skipped, None);
+            }
+
+            let started = Instant::now();
+            let outcome = run(command, prefix, cancelled).await;
+            if fail_fast && !matches!(outcome, Outcome::Exited(0)) {
+                let _ = cancel.broadcast(true);
+            }
+            (outcome, Some(started.elapsed()))
+        }));
+    }
+
+    let mut results = Vec::new();
+    for handle in handles {
+        results.push(match handle.await {
+            Ok(result) => result,
+            Err(e) => (Outcome::Failed(e.to_string()), None),
+        });
+    }
+
+    if let Err(e) = directory.close() {
+        leg::error(
+            &format!("Failed to remove temporary certificates: {}", e),
+            None,
+            None,
+        );
+    }
+    for chain in chains {
+        chain.close();
+    }
+
+    // Summarize how the command ended on every profile
+    println!();
+    println!(
+        "{:width$}  {:<10}  DURATION",
+        "PROFILE",
+        "STATUS",
+        width = width
+    );
+    let mut failures = 0;
+    for (target, (outcome, duration)) in targets.iter().zip(&results) {
+        let status = match outcome {
+            Outcome::Exited(code) => code.to_string(),
+            Outcome::Failed(_) => String::from("failed"),
+            Outcome::Cancelled => String::from("cancelled"),
+            Outcome::Skipped => String::from("skipped"),
+        };
+        let duration = duration
+            .as_ref()
+            .map_or_else(|| String::from("-"), format_elapsed);
+        println!(
+            "{:width$}  {:<10}  {}",
+            target.name,
+            status,
+            duration,
+            width = width
+        );
+
+        if let Outcome::Failed(e) = outcome {
+            leg::error(
+                &format!("Failed to run ssh for '{}': {}", target.name, e),
+                None,
+                None,
+            );
+        }
+        if !matches!(outcome, Outcome::Exited(0)) {
+            failures += 1;
+        }
+    }
+
+    if failures == 0 {
+        leg::success(
+            &format!("Command succeeded on all {} profiles", results.len()),
+            None,
+            None,
+        );
+    } else {
+        leg::error(
+            &format!(
+                "Command did not succeed on {} of {} profiles",
+                failures,
+                results.len()
+            ),
+            None,
+            None,
+        );
+        exit(1);
+    }
+}
+
+/// Run ssh, prefixing every line of its output, until it exits or is cancelled
+async fn run(
+    mut command: Command,
+    prefix: String,
+    mut cancelled: watch::Receiver<bool>,
+) -> Outcome {
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => return Outcome::Failed(e.to_string()),
+    };
+    let pid = child.id() as libc::pid_t;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let wait = async {
+        tokio::select! {
+            status = &mut child => (status, false),
+            _ = wait_cancelled(&mut cancelled) => {
+                // ssh closes the connection when terminated
+                unsafe {
+                    libc::kill(pid, libc::SIGTERM);
+                }
+                ((&mut child).await, true)
+            }
+        }
+    };
+    let ((status, was_cancelled), _, _) = tokio::join!(
+        wait,
+        prefix_lines(stdout, &prefix, false),
+        prefix_lines(stderr, &prefix, true)
+    );
+
+    match status {
+        _ if was_cancelled => Outcome::Cancelled,
+        Ok(status) => Outcome::Exited(
+            status
+                .code()
+                .or_else(|| status.signal().map(|signal| 128 + signal))
+                .unwrap_or(1),
+        ),
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+/// Wait until cancelled, never returning if the sender is dropped first
+async fn wait_cancelled(cancelled: &mut watch::Receiver<bool>) {
+    while let Some(cancel) = cancelled.recv().await {
+        if cancel {
+            return;
+        }
+    }
+    pending().await
+}
+
+/// Print every line of a stream with a prefix
+async fn prefix_lines<R: AsyncRead + Unpin>(stream: Option<R>, prefix: &str, stderr: bool) {
+    let mut reader = match stream {
+        Some(stream) => BufReader::new(stream),
+        None => return,
+    };
+
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(&['\r', '\n'][..]);
+        let _ = if stderr {
+            writeln!(io::stderr(), "{}{}", prefix, text)
+        } else {
+            writeln!(io::stdout(), "{}{}", prefix, text)
+        };
+    }
+}
+
+/// Format how long a command ran for, i.e. `1.25s`
+fn format_elapsed(elapsed: &Duration) -> String {
+    format!("{:.2}s", elapsed.as_secs_f64())
+}
@@ -0,0 +1,162 @@
+use crate::api::ApiClient;
+use crate::config::SignOptions;
+use crate::sshd;
+use crate::util::fail;
+use std::fs::{read_dir, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub async fn sign(
+    client: &ApiClient,
+    role: String,
+    keys_dir: String,
+    principals: Option<String>,
+    ttl: Option<String>,
+    apply: bool,
+    sshd_config: String,
+) {
+    // Find the host's public keys
+    let keys = host_keys(Path::new(&keys_dir));
+    if keys.is_empty() {
+        fail(&format!("No host keys found in '{}'", keys_dir));
+    }
+
+    // Default to every name the host is known by
+    let principals = principals.unwrap_or_else(|| {
+        let names = fqdns();
+        if names.is_empty() {
+            fail("Unable to determine the host's FQDNs, use '--principals' to set them");
+        }
+        names.join(",")
+    });
+    leg::info(
+        &format!("Requesting certificates for: {}", principals),
+        None,
+        None,
+    );
+
+    let options = SignOptions {
+        valid_principals: Some(principals),
+        ttl,
+        cert_type: Some(String::from("host")),
+        ..SignOptions::default()
+    };
+
+    // Sign each key and write the certificate next to it
+    let mut certificates = Vec::new();
+    for key in keys {
+        let contents = match read_to_string(&key) {
+            Ok(contents) => contents,
+            Err(e) => fail(&format!("Failed to read '{}': {}", key.display(), e)),
+        };
+
+        let signed = match client.sign(role.clone(), contents, &options).await {
+            Ok(signed) => signed,
+            Err(e) => fail(&format!("Failed to sign '{}': {}", key.display(), e)),
+        };
+
+        let certificate = certificate_path(&key);
+        if let Err(e) = write(&certificate, signed) {
+            fail(&format!(
+                "Failed to write '{}': {}",
+                certificate.display(),
+                e
+            ));
+        }
+
+        leg::success(
+            &format!("Wrote host certificate to: {}", certificate.display()),
+            None,
+            None,
+        );
+        certificates.push(certificate);
+    }
+
+    // Print the directives for the operator to add themselves
+    if !apply {
+        for certificate in &certificates {
+            println!("HostCertificate {}", certificate.display());
+        }
+        return;
+    }
+
+    // Add any missing directives to the sshd configuration
+    let contents = match read_to_string(&sshd_config) {
+        Ok(contents) => contents,
+        Err(e) => fail(&format!("Failed to read '{}': {}", sshd_config, e)),
+    };
+    let updated = certificates
+        .iter()
+        .fold(contents.clone(), |contents, path| {
+            sshd::add_directive(&contents, "HostCertificate", &path.display().to_string())
+        });
+
+    if updated == contents {
+        leg::info(
+            "sshd is already configured to use the certificates",
+            None,
+            None,
+        );
+        return;
+    }
+
+    match write(&sshd_config, updated) {
+        Ok(_) => leg::success(
+            &format!(
+                "Updated '{}', reload sshd to present the certificates",
+                sshd_config
+            ),
+            None,
+            None,
+        ),
+        Err(e) => fail(&format!("Failed to write '{}': {}", sshd_config, e)),
+    }
+}
+
+/// Find the `ssh_host_*_key.pub` files in a directory, sorted by name
+fn host_keys(directory: &Path) -> Vec<PathBuf> {
+    let entries = match read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => fail(&format!("Failed to read '{}': {}", directory.display(), e)),
+    };
+
+    let mut keys = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("ssh_host_") && name.ends_with("_key.pub"))
+        })
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys
+}
+
+/// Get the path of the certificate for a public key, i.e. `ssh_host_ed25519_key-cert.pub`
+fn certificate_path(key: &Path) -> PathBuf {
+    let name = key
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .trim_end_matches(".pub");
+    key.with_file_name(format!("{}-cert.pub", name))
+}
+
+/// Get the fully qualified domain names of the host without duplicates
+fn fqdns() -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for flag in &["-f", "-A"] {
+        let output = match Command::new("hostname").arg(flag).output() {
+            Ok(output) if output.status.success() => output,
+            _ => continue,
+        };
+
+        for name in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+            if name.contains('.') && !names.iter().any(|existing| existing == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
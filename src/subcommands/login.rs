@@ -0,0 +1,108 @@
+use crate::api::ApiClient;
+use crate::auth;
+use crate::config::{Auth, Config};
+use crate::environment::VaultEnvironment;
+use crate::util::{fail, format_duration};
+
+#[allow(clippy::too_many_arguments)]
+pub async fn login(
+    config: &Config,
+    environment: &VaultEnvironment,
+    namespace: Option<String>,
+    method: Option<String>,
+    mount: Option<String>,
+    username: Option<String>,
+    role: Option<String>,
+    port: Option<u16>,
+) {
+    // Use the configured method if none is specified
+    let method = method.unwrap_or_else(|| match &config.auth {
+        Some(Auth::Ldap { .. }) => String::from("ldap"),
+        Some(Auth::Oidc { .. }) => String::from("oidc"),
+        _ => String::from("userpass"),
+    });
+
+    // Start from the configured values when the methods match
+    let mut auth = match &config.auth {
+        Some(auth) if auth.method() == method => auth.clone(),
+        _ => default_auth(&method),
+    };
+
+    // Apply any overrides
+    match &mut auth {
+        Auth::Userpass {
+            mount: current_mount,
+            username: current_username,
+        }
+        | Auth::Ldap {
+            mount: current_mount,
+            username: current_username,
+        } => {
+            if let Some(mount) = mount {
+                *current_mount = mount;
+            }
+            if let Some(username) = username {
+                *current_username = username;
+            }
+        }
+        Auth::Oidc {
+            mount: current_mount,
+            role: current_role,
+            port: current_port,
+        } => {
+            if let Some(mount) = mount {
+                *current_mount = mount;
+            }
+            if role.is_some() {
+                *current_role = role;
+            }
+            if let Some(port) = port {
+                *current_port = port;
+            }
+        }
+        Auth::AppRole { .. } => {}
+    }
+
+    // Log in and cache the token
+    let mut client = ApiClient::from_config(config, environment);
+    if let Some(namespace) = namespace {
+        client = client.with_namespace(&namespace);
+    }
+    match auth::login(&mut client, &auth, config.auth.is_none()).await {
+        Ok(_) => leg::success("Successfully logged in", None, None),
+        Err(e) => fail(&format!("Failed to log in: {}", e)),
+    }
+
+    // Show what the token grants
+    if let Ok(Some(info)) = client.validate().await {
+        let ttl = if info.ttl == 0 {
+            String::from("never expires")
+        } else {
+            format!("expires in {}", format_duration(info.ttl))
+        };
+        leg::info(
+            &format!("Token {} with policies: {}", ttl, info.policies.join(", ")),
+            None,
+            None,
+        );
+    }
+}
+
+/// Get the default settings for a method
+fn default_auth(method: &str) -> Auth {
+    match method {
+        "ldap" => Auth::Ldap {
+            mount: String::from("ldap"),
+            username: whoami::username(),
+        },
+        "oidc" => Auth::Oidc {
+            mount: String::from("oidc"),
+            role: None,
+            port: auth::DEFAULT_CALLBACK_PORT,
+        },
+        _ => Auth::Userpass {
+            mount: String::from("userpass"),
+            username: whoami::username(),
+        },
+    }
+}
@@ -1,11 +1,32 @@
+mod agent;
+mod agent_add;
+pub mod ca;
+pub mod cache;
+pub mod cert;
+mod clients;
 mod connect;
+mod copy;
+mod exec;
+pub mod host;
 mod list;
+mod login;
 pub mod profiles;
+mod proxy;
 mod repair_config;
+pub mod server;
 pub mod setup;
 mod sign;
+pub mod ssh_config;
+mod tunnel;
 
-pub use connect::connect;
+pub use agent::agent;
+pub use agent_add::agent_add;
+pub use connect::{connect, exit_with, parse_options, Transport};
+pub use copy::copy;
+pub use exec::exec;
 pub use list::list;
+pub use login::login;
+pub use proxy::{find_profile, proxy};
 pub use repair_config::repair_config;
 pub use sign::sign;
+pub use tunnel::tunnel;
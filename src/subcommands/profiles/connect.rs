@@ -1,9 +1,31 @@
 use crate::api::ApiClient;
-use crate::config::Config;
-use crate::subcommands::connect::connect as connect_subcommand;
+use crate::cache::DEFAULT_CACHE_MARGIN;
+use crate::config::{Config, Profile, SignOptions};
+use crate::errors::SignError;
+use crate::subcommands::clients::Clients;
+use crate::subcommands::connect::{
+    connect as connect_subcommand, exit_with, parse_options, Transport,
+};
+use crate::subcommands::profiles::jump_hosts;
 use crate::util::fail;
+use std::future::Future;
 
-pub async fn connect(name: String, sftp: bool, client: &ApiClient, config: &Config) {
+#[allow(clippy::too_many_arguments)]
+pub async fn connect<F, Fut>(
+    name: String,
+    transport: Transport,
+    remote_command: Vec<String>,
+    ephemeral: bool,
+    agent: bool,
+    sign_options: SignOptions,
+    path: Option<String>,
+    namespace: Option<String>,
+    config: &Config,
+    authenticate: F,
+) where
+    F: Fn(Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<ApiClient, SignError>>,
+{
     // Ensure profile exists
     let profile = match config.profiles.get(&name) {
         Some(profile) => profile,
@@ -12,15 +34,55 @@ pub async fn connect(name: String, sftp: bool, client: &ApiClient, config: &Conf
 
     leg::success("Retrieved selected profile", None, None);
 
+    let cache_margin = config.cache_margin.unwrap_or(DEFAULT_CACHE_MARGIN);
+    let mut clients = Clients::new(authenticate);
+    let jump_hosts = match jump_hosts(config, &name, profile, &mut clients, cache_margin).await {
+        Ok(jump_hosts) => jump_hosts,
+        Err(e) => fail(&e.to_string()),
+    };
+
+    // Command line overrides take precedence over the profile's settings
+    let path = path.or_else(|| profile.path.clone());
+    let namespace = namespace.or_else(|| profile.namespace.clone());
+    let client = match clients.get(path, namespace).await {
+        Ok(client) => client,
+        Err(e) => fail(&e.to_string()),
+    };
+
     // Run using same subcommand
-    connect_subcommand(
+    let status = connect_subcommand(
         client,
         profile.role.clone(),
         profile.private_key.clone(),
         profile.public_key.clone(),
-        format!("{}@{}", profile.username, profile.address),
-        sftp,
-        profile.options.clone(),
+        transport,
+        profile_options(profile, transport),
+        transport.destination(
+            format!("{}@{}", profile.username, profile.address),
+            remote_command,
+        ),
+        jump_hosts.as_ref(),
+        ephemeral || profile.ephemeral,
+        agent,
+        sign_options.or(&profile.sign_options),
+        cache_margin,
     )
     .await;
+    exit_with(status);
+}
+
+/// The ssh options for connecting to a profile with a transport.
+/// The port and jump host are passed as options.
+pub fn profile_options(profile: &Profile, transport: Transport) -> Vec<String> {
+    let mut options = Vec::new();
+    if let Some(port) = profile.port {
+        options.push(String::from(transport.port_flag()));
+        options.push(port.to_string());
+    }
+    if let Some(proxy_jump) = &profile.proxy_jump {
+        options.push(String::from("-J"));
+        options.push(proxy_jump.clone());
+    }
+    options.extend(parse_options(&profile.options));
+    options
 }
@@ -1,14 +1,27 @@
-use crate::config::{Config, Profile};
+use crate::config::{Config, DynamicForward, Forward, Profile, SignOptions};
+use crate::subcommands::parse_options;
+use crate::subcommands::profiles::check_jump_hosts;
 use crate::util::fail;
 
+#[allow(clippy::too_many_arguments)]
 pub fn create(
     name: String,
     username: Option<String>,
     address: String,
+    port: Option<u16>,
+    proxy_jump: Option<String>,
+    jump: Vec<String>,
     role: String,
     private_key: Option<String>,
     public_key: Option<String>,
     options: String,
+    path: Option<String>,
+    namespace: Option<String>,
+    ephemeral: bool,
+    local_forwards: Vec<Forward>,
+    remote_forwards: Vec<Forward>,
+    dynamic_forward: Option<DynamicForward>,
+    sign_options: SignOptions,
     mut config: Config,
 ) {
     // Get default username if not provided
@@ -23,16 +36,32 @@ pub fn create(
         fail(&format!("Profile '{}' already exists", name));
     }
 
+    // Ensure the options can be passed to ssh
+    parse_options(&options);
+
+    // Ensure the jump hosts are profiles
+    check_jump_hosts(&config, &name, &jump);
+
     // Add profile to configuration
     config.profiles.insert(
         name.clone(),
         Profile {
             username,
             address,
+            port,
+            proxy_jump,
+            jump,
             role,
             private_key,
             public_key,
             options,
+            path,
+            namespace,
+            sign_options,
+            ephemeral,
+            local_forwards,
+            remote_forwards,
+            dynamic_forward,
         },
     );
 
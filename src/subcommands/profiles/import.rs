@@ -0,0 +1,117 @@
+use crate::config::{Config, Profile, SignOptions};
+use crate::ssh_config::{host_matches, read_hosts, HostEntry};
+use crate::subcommands::setup::prompt_bool;
+use crate::util::fail;
+use std::path::Path;
+
+pub fn import(
+    from_ssh_config: String,
+    role: Option<String>,
+    role_map: Vec<(String, String)>,
+    yes: bool,
+    mut config: Config,
+) {
+    let hosts = match read_hosts(Path::new(&from_ssh_config)) {
+        Ok(hosts) => hosts,
+        Err(e) => fail(&format!("Failed to read ssh configuration: {}", e)),
+    };
+
+    // Hosts without a role or with an existing profile are skipped
+    let mut profiles = Vec::new();
+    for host in hosts {
+        if config.profiles.contains_key(&host.alias) {
+            leg::warn(
+                &format!("Skipping '{}', a profile already exists", host.alias),
+                None,
+                None,
+            );
+            continue;
+        }
+
+        let role = role_map
+            .iter()
+            .find(|(pattern, _)| {
+                host_matches(
+                    &pattern.split(',').map(String::from).collect::<Vec<_>>(),
+                    &host.alias,
+                )
+            })
+            .map(|(_, role)| role.clone())
+            .or_else(|| role.clone());
+        match role {
+            Some(role) => profiles.push((host.alias.clone(), profile(host, role))),
+            None => leg::warn(
+                &format!("Skipping '{}', no role matches it", host.alias),
+                None,
+                None,
+            ),
+        }
+    }
+
+    if profiles.is_empty() {
+        leg::info("No profiles to import", None, None);
+        return;
+    }
+
+    // Preview the profiles before writing them
+    for (name, profile) in &profiles {
+        let mut line = format!("{}: {}@{}", name, profile.username, profile.address);
+        if let Some(port) = profile.port {
+            line.push_str(&format!(":{}", port));
+        }
+        line.push_str(&format!(" as '{}'", profile.role));
+        if let Some(private_key) = &profile.private_key {
+            line.push_str(&format!(" with {}", private_key));
+        }
+        if let Some(proxy_jump) = &profile.proxy_jump {
+            line.push_str(&format!(" via {}", proxy_jump));
+        }
+        println!("{}", line);
+    }
+
+    if !yes && !prompt_bool("Import these profiles?", false) {
+        leg::info("Did not import any profiles", None, None);
+        return;
+    }
+
+    let count = profiles.len();
+    config.profiles.extend(profiles);
+    match config.write() {
+        Ok(_) => leg::success(&format!("Imported {} profiles", count), None, None),
+        Err(e) => fail(&format!("Failed to write to configuration file: {}", e)),
+    }
+}
+
+/// Create a profile for a host, using the current user and the alias as the address by default
+fn profile(host: HostEntry, role: String) -> Profile {
+    // vssh does not expand the home directory itself
+    let private_key = host
+        .identity_file
+        .map(|path| match path.strip_prefix("~/") {
+            Some(relative) => dirs::home_dir()
+                .expect("Failed to retrieve user's home directory")
+                .join(relative)
+                .display()
+                .to_string(),
+            None => path,
+        });
+
+    Profile {
+        username: host.user.unwrap_or_else(whoami::username),
+        address: host.hostname.unwrap_or(host.alias),
+        port: host.port,
+        proxy_jump: host.proxy_jump,
+        jump: Vec::new(),
+        role,
+        private_key,
+        public_key: None,
+        options: String::new(),
+        path: None,
+        namespace: None,
+        sign_options: SignOptions::default(),
+        ephemeral: false,
+        local_forwards: Vec::new(),
+        remote_forwards: Vec::new(),
+        dynamic_forward: None,
+    }
+}
@@ -0,0 +1,228 @@
+use crate::api::ApiClient;
+use crate::config::{Config, Profile};
+use crate::errors::SignError;
+use crate::subcommands::clients::Clients;
+use crate::subcommands::connect::{
+    create_private_dir, sign_profile, write_identity, Key, Transport,
+};
+use crate::subcommands::profiles::profile_options;
+use crate::util::fail;
+use std::future::Future;
+use std::io;
+use tempfile::TempDir;
+
+/// Certificates signed for the jump hosts in front of a profile, which are only written
+/// to temporary files once they are needed to connect
+pub struct JumpHosts<'a> {
+    target: &'a Profile,
+    hops: Vec<(&'a Profile, Key, String)>,
+}
+
+impl<'a> JumpHosts<'a> {
+    /// The profile the jump hosts were signed for
+    pub fn target(&self) -> &'a Profile {
+        self.target
+    }
+
+    /// Write the certificates and ephemeral keys to a private directory, chaining them
+    /// with ProxyCommand so each hop authenticates with its own. Nothing is left behind
+    /// if writing fails.
+    pub fn write(&self) -> io::Result<JumpChain> {
+        // Every hop reaches the next one through the command of the hop before it
+        let directory = create_private_dir()?;
+        let mut proxy_command: Option<String> = None;
+        let mut last_hop = Vec::new();
+        for (index, (hop, key, certificate)) in self.hops.iter().enumerate() {
+            let [private_key, certificate] =
+                write_identity(directory.path(), &index.to_string(), key, certificate)?;
+            let mut command = vec![
+                String::from("ssh"),
+                String::from("-i"),
+                private_key.display().to_string(),
+                String::from("-i"),
+                certificate.display().to_string(),
+            ];
+            if let Some(previous) = &proxy_command {
+                command.push(String::from("-o"));
+                command.push(proxy_option(previous));
+            }
+            command.extend(profile_options(hop, Transport::Ssh));
+
+            // The last hop forwards to whichever target the chain is used for
+            let address = format!("{}@{}", hop.username, hop.address);
+            match self.hops.get(index + 1) {
+                Some((next, _, _)) => {
+                    proxy_command = Some(forward_command(&command, next, &address))
+                }
+                None => last_hop = command,
+            }
+        }
+        leg::success(
+            "Wrote jump host certificates to temporary files",
+            None,
+            None,
+        );
+
+        let (last, _, _) = self.hops.last().unwrap();
+        Ok(JumpChain {
+            directory,
+            last_hop,
+            last_address: format!("{}@{}", last.username, last.address),
+        })
+    }
+}
+
+/// Certificates for the jump hosts in front of a profile and the command that connects
+/// through them. The certificates are removed once the chain is closed.
+pub struct JumpChain {
+    directory: TempDir,
+    /// Command connecting to the last jump host, which forwards to the target
+    last_hop: Vec<String>,
+    last_address: String,
+}
+
+impl JumpChain {
+    /// The ssh options that connect to a target through the jump hosts
+    pub fn options(&self, target: &Profile) -> Vec<String> {
+        let command = forward_command(&self.last_hop, target, &self.last_address);
+        vec![String::from("-o"), proxy_option(&command)]
+    }
+
+    /// Remove the certificates and ephemeral keys of the jump hosts,
+    /// only reporting failures so that the exit status is still propagated
+    pub fn close(self) {
+        match self.directory.close() {
+            Ok(_) => leg::success("Cleaned up jump host certificates", None, None),
+            Err(e) => leg::error(
+                &format!("Failed to remove jump host certificates: {}", e),
+                None,
+                None,
+            ),
+        }
+    }
+}
+
+/// Sign a certificate for every jump host in front of a profile with the hop's own role,
+/// SSH CA and key. Returns None if the profile has no jump hosts.
+pub async fn jump_hosts<'a, F, Fut>(
+    config: &'a Config,
+    name: &str,
+    profile: &'a Profile,
+    clients: &mut Clients<F>,
+    cache_margin: u64,
+) -> Result<Option<JumpHosts<'a>>, SignError>
+where
+    F: Fn(Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<ApiClient, SignError>>,
+{
+    if profile.jump.is_empty() {
+        return Ok(None);
+    }
+
+    let mut hops = Vec::new();
+    collect_hops(config, profile, &mut vec![name], &mut hops);
+
+    check_proxy_jump(name, profile);
+    if let Some((hop, _)) = hops
+        .iter()
+        .skip(1)
+        .find(|(_, hop)| hop.proxy_jump.is_some())
+    {
+        fail(&format!(
+            "Jump host '{}' has a proxy jump, which only the first jump host may have",
+            hop
+        ));
+    }
+
+    let mut signed = Vec::new();
+    for (hop_name, hop) in hops {
+        leg::info(
+            &format!("Signing certificate for jump host '{}'", hop_name),
+            None,
+            None,
+        );
+        let client = clients.get(hop.path.clone(), hop.namespace.clone()).await?;
+        let (key, certificate) = sign_profile(client, hop, cache_margin).await?;
+        signed.push((hop, key, certificate));
+    }
+
+    Ok(Some(JumpHosts {
+        target: profile,
+        hops: signed,
+    }))
+}
+
+/// Ensure the jump hosts of a profile are other existing profiles
+pub fn check_jump_hosts(config: &Config, name: &str, jump: &[String]) {
+    for hop in jump {
+        if hop == name {
+            fail(&format!("Profile '{}' cannot be its own jump host", name));
+        }
+        if !config.profiles.contains_key(hop) {
+            fail(&format!("Jump host profile '{}' does not exist", hop));
+        }
+    }
+}
+
+/// Ensure a profile with jump hosts has no proxy jump, since ssh refuses ProxyJump
+/// together with ProxyCommand, which only the first hop goes without
+pub fn check_proxy_jump(name: &str, profile: &Profile) {
+    if !profile.jump.is_empty() && profile.proxy_jump.is_some() {
+        fail(&format!(
+            "Profile '{}' has both jump hosts and a proxy jump, remove one of them",
+            name
+        ));
+    }
+}
+
+/// Collect the jump hosts of a profile in the order they are connected through,
+/// where the jump hosts of a hop come before it
+fn collect_hops<'a: 'b, 'b>(
+    config: &'a Config,
+    profile: &'a Profile,
+    visiting: &mut Vec<&'b str>,
+    hops: &mut Vec<(&'a str, &'a Profile)>,
+) {
+    for name in &profile.jump {
+        if visiting.contains(&name.as_str()) {
+            fail(&format!(
+                "Jump hosts form a cycle: {} -> {}",
+                visiting.join(" -> "),
+                name
+            ));
+        }
+        let hop = match config.profiles.get(name) {
+            Some(hop) => hop,
+            None => fail(&format!("Jump host profile '{}' does not exist", name)),
+        };
+
+        visiting.push(name);
+        collect_hops(config, hop, visiting, hops);
+        visiting.pop();
+        hops.push((name, hop));
+    }
+}
+
+/// Command connecting to a jump host that forwards to the next host
+fn forward_command(hop: &[String], next: &Profile, address: &str) -> String {
+    let mut command = hop.to_vec();
+    command.push(String::from("-W"));
+    command.push(forward_address(next));
+    command.push(address.to_string());
+    shell_words::join(&command)
+}
+
+/// The address and port ssh forwards to with `-W`, bracketing IPv6 addresses
+fn forward_address(profile: &Profile) -> String {
+    let port = profile.port.unwrap_or(22);
+    if profile.address.contains(':') {
+        format!("[{}]:{}", profile.address, port)
+    } else {
+        format!("{}:{}", profile.address, port)
+    }
+}
+
+/// ProxyCommand option running a command, where ssh would otherwise expand `%` tokens in it
+fn proxy_option(command: &str) -> String {
+    format!("ProxyCommand={}", command.replace('%', "%%"))
+}
@@ -1,13 +1,17 @@
 mod connect;
 mod create;
 mod delete;
+mod import;
+mod jump;
 mod list;
 mod read;
 mod update;
 
-pub use connect::connect;
+pub use connect::{connect, profile_options};
 pub use create::create;
 pub use delete::delete;
+pub use import::import;
+pub use jump::{check_jump_hosts, check_proxy_jump, jump_hosts, JumpHosts};
 pub use list::list;
 pub use read::read;
 pub use update::update;
@@ -1,18 +1,34 @@
-use crate::config::Config;
+use crate::config::{Config, Forward};
 use crate::util::fail;
+use std::collections::HashMap;
 
 pub fn read(name: String, config: Config) {
     // Print data if entry exists
     match config.profiles.get(&name) {
         Some(profile) => leg::success(
             &format!(
-            "{}:\n\tUsername: {}\n\tRole: {}\n\tAddress: {}\n\tPrivate Key: {}\n\tPublic Key: {}",
+            "{}:\n\tUsername: {}\n\tRole: {}\n\tAddress: {}\n\tPort: {}\n\tProxy Jump: {}\n\tJump Hosts: {}\n\tPrivate Key: {}\n\tPublic Key: {}\n\tEphemeral Keys: {}\n\tLocal Forwards: {}\n\tRemote Forwards: {}\n\tSOCKS Proxy: {}\n\tSSH CA Path: {}\n\tNamespace: {}\n\tPrincipals: {}\n\tTTL: {}\n\tCertificate Type: {}\n\tKey ID: {}\n\tCritical Options: {}\n\tExtensions: {}",
             name,
             profile.username,
             profile.role,
             profile.address,
+            profile.port.map_or_else(|| "Default".to_string(), |port| port.to_string()),
+            profile.proxy_jump.as_ref().unwrap_or(&"None".to_string()),
+            if profile.jump.is_empty() { String::from("None") } else { profile.jump.join(", ") },
             profile.private_key.as_ref().unwrap_or(&"None".to_string()),
-            profile.public_key.as_ref().unwrap_or(&"None".to_string())
+            profile.public_key.as_ref().unwrap_or(&"None".to_string()),
+            profile.ephemeral,
+            format_forwards(&profile.local_forwards),
+            format_forwards(&profile.remote_forwards),
+            profile.dynamic_forward.as_ref().map_or_else(|| "None".to_string(), |forward| forward.to_string()),
+            profile.path.as_ref().unwrap_or(&"Default".to_string()),
+            profile.namespace.as_ref().unwrap_or(&"Default".to_string()),
+            profile.sign_options.valid_principals.as_ref().unwrap_or(&"Default".to_string()),
+            profile.sign_options.ttl.as_ref().unwrap_or(&"Default".to_string()),
+            profile.sign_options.cert_type.as_ref().unwrap_or(&"Default".to_string()),
+            profile.sign_options.key_id.as_ref().unwrap_or(&"Default".to_string()),
+            format_pairs(&profile.sign_options.critical_options),
+            format_pairs(&profile.sign_options.extensions)
         ),
             None,
             None,
@@ -20,3 +36,36 @@ pub fn read(name: String, config: Config) {
         None => fail(&format!("Profile '{}' does not exist", name)),
     }
 }
+
+/// Format forwards in the order they are opened
+fn format_forwards(forwards: &[Forward]) -> String {
+    if forwards.is_empty() {
+        return String::from("None");
+    }
+
+    forwards
+        .iter()
+        .map(|forward| forward.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format key=value pairs in a stable order
+fn format_pairs(pairs: &HashMap<String, String>) -> String {
+    if pairs.is_empty() {
+        return String::from("Default");
+    }
+
+    let mut formatted = pairs
+        .iter()
+        .map(|(key, value)| {
+            if value.is_empty() {
+                key.clone()
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect::<Vec<_>>();
+    formatted.sort();
+    formatted.join(", ")
+}
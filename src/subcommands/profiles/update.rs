@@ -1,14 +1,30 @@
-use crate::config::Config;
+use crate::config::{Config, DynamicForward, Forward, SignOptions};
+use crate::subcommands::parse_options;
+use crate::subcommands::profiles::check_jump_hosts;
 use crate::util::fail;
 
+#[allow(clippy::too_many_arguments)]
 pub fn update(
     name: String,
     username: Option<String>,
     address: Option<String>,
+    port: Option<u16>,
+    proxy_jump: Option<String>,
+    jump: Vec<String>,
+    no_jump: bool,
     role: Option<String>,
     private_key: Option<String>,
     public_key: Option<String>,
     options: Option<String>,
+    path: Option<String>,
+    namespace: Option<String>,
+    ephemeral: Option<bool>,
+    local_forwards: Vec<Forward>,
+    remote_forwards: Vec<Forward>,
+    dynamic_forward: Option<DynamicForward>,
+    clear_forwards: bool,
+    clear_sign_options: bool,
+    sign_options: SignOptions,
     mut config: Config,
 ) {
     // Ensure profile exists
@@ -16,6 +32,14 @@ pub fn update(
         fail(&format!("Profile '{}' does not exist", name));
     }
 
+    // Ensure the options can be passed to ssh
+    if let Some(options) = &options {
+        parse_options(options);
+    }
+
+    // Ensure the jump hosts are profiles
+    check_jump_hosts(&config, &name, &jump);
+
     // Update entry in place
     config.profiles.entry(name.clone()).and_modify(|profile| {
         // Set username if exists
@@ -28,6 +52,23 @@ pub fn update(
             profile.address = address;
         }
 
+        // Set port if exists
+        if let Some(port) = port {
+            profile.port = Some(port);
+        }
+
+        // Set jump host if exists
+        if let Some(proxy_jump) = proxy_jump {
+            profile.proxy_jump = Some(proxy_jump);
+        }
+
+        // Replace or clear the jump host profiles if requested
+        if no_jump {
+            profile.jump.clear();
+        } else if !jump.is_empty() {
+            profile.jump = jump;
+        }
+
         // Set role if exists
         if let Some(role) = role {
             profile.role = role;
@@ -47,6 +88,43 @@ pub fn update(
         if let Some(options) = options {
             profile.options = options;
         }
+
+        // Set SSH CA path if exists
+        if let Some(path) = path {
+            profile.path = Some(path);
+        }
+
+        // Set namespace if exists
+        if let Some(namespace) = namespace {
+            profile.namespace = Some(namespace);
+        }
+
+        // Set whether to use ephemeral keys if exists
+        if let Some(ephemeral) = ephemeral {
+            profile.ephemeral = ephemeral;
+        }
+
+        // Remove the forwards if requested before adding any new ones
+        if clear_forwards {
+            profile.local_forwards.clear();
+            profile.remote_forwards.clear();
+            profile.dynamic_forward = None;
+        }
+        profile.local_forwards.extend(local_forwards);
+        profile.remote_forwards.extend(remote_forwards);
+
+        // Set SOCKS proxy port if exists
+        if let Some(dynamic_forward) = dynamic_forward {
+            profile.dynamic_forward = Some(dynamic_forward);
+        }
+
+        // Remove the signing parameters if requested before setting any new ones
+        if clear_sign_options {
+            profile.sign_options = SignOptions::default();
+        }
+
+        // Set any provided signing parameters
+        profile.sign_options = sign_options.clone().or(&profile.sign_options);
     });
 
     // Write to file
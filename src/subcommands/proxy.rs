@@ -0,0 +1,154 @@
+use crate::api::ApiClient;
+use crate::config::{Config, Profile, SignOptions};
+use crate::ssh::{Certificate, KeyPair};
+use crate::subcommands::connect::{add_to_agent, existing_key, sign, sign_cached, EPHEMERAL_TTL};
+use crate::util::fail;
+use std::fs::{read_to_string, write};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::process::exit;
+use std::thread;
+
+/// Find the profile to connect with, either by name or by the address of the host
+pub fn find_profile<'a>(config: &'a Config, name: Option<&str>, host: &str) -> &'a Profile {
+    if let Some(name) = name {
+        return match config.profiles.get(name) {
+            Some(profile) => profile,
+            None => fail(&format!("Profile '{}' does not exist", name)),
+        };
+    }
+
+    let mut matching = config
+        .profiles
+        .iter()
+        .filter(|(_, profile)| profile.address.eq_ignore_ascii_case(host))
+        .collect::<Vec<_>>();
+    matching.sort_by_key(|(name, _)| name.as_str());
+
+    match matching.as_slice() {
+        [(_, profile)] => profile,
+        [] => fail(&format!(
+            "No profile has the address '{}', use '--role' or '--profile'",
+            host
+        )),
+        _ => fail(&format!(
+            "Multiple profiles have the address '{}': {}, use '--profile' to choose one",
+            host,
+            matching
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn proxy(
+    client: &ApiClient,
+    host: String,
+    port: u16,
+    role: String,
+    private_key: Option<String>,
+    public_key: Option<String>,
+    ephemeral: bool,
+    check: bool,
+    mut sign_options: SignOptions,
+    cache_margin: u64,
+) {
+    if ephemeral {
+        // ssh can only find an ephemeral key through the agent
+        if sign_options.ttl.is_none() {
+            sign_options.ttl = Some(String::from(EPHEMERAL_TTL));
+        }
+
+        let key = match KeyPair::generate("vssh-ephemeral") {
+            Ok(key) => key,
+            Err(e) => fail(&format!("Failed to generate key pair: {}", e)),
+        };
+        leg::success("Generated ephemeral key pair", None, None);
+
+        let signed = sign(client, &role, key.public_key(), &sign_options).await;
+        add_to_agent(&key.private_key(), &signed, "vssh-ephemeral");
+    } else {
+        // ssh loads identities after the proxy connects, so it finds the certificate next to the key
+        let (private_path, contents) = existing_key(private_key, public_key);
+        let signed = sign_cached(client, &role, contents, &sign_options, cache_margin).await;
+
+        let certificate = format!("{}-cert.pub", private_path.display());
+        let existing = read_to_string(&certificate).ok();
+        if let Some(existing) = &existing {
+            // Certificates from another CA may still be needed for other servers
+            if !same_ca(existing, &signed) {
+                fail(&format!(
+                    "'{}' was not signed by the SSH CA, move it away so the certificate can be written",
+                    certificate
+                ));
+            }
+        }
+
+        if existing.as_deref() != Some(signed.as_str()) {
+            match write(&certificate, &signed) {
+                Ok(_) => leg::success(
+                    &format!("Wrote certificate to: {}", certificate),
+                    None,
+                    None,
+                ),
+                Err(e) => fail(&format!("Failed to write '{}': {}", certificate, e)),
+            }
+        }
+    }
+
+    if check {
+        return;
+    }
+
+    let stream = match TcpStream::connect((host.as_str(), port)) {
+        Ok(stream) => stream,
+        Err(e) => fail(&format!("Failed to connect to {}:{}: {}", host, port, e)),
+    };
+    relay(stream);
+}
+
+/// Whether two certificates were signed by the same CA
+fn same_ca(first: &str, second: &str) -> bool {
+    match (Certificate::parse(first), Certificate::parse(second)) {
+        (Ok(first), Ok(second)) => first.ca_fingerprint == second.ca_fingerprint,
+        _ => false,
+    }
+}
+
+/// Copy stdin to the connection and the connection to stdout until the server closes it
+fn relay(stream: TcpStream) -> ! {
+    let mut upload = match stream.try_clone() {
+        Ok(upload) => upload,
+        Err(e) => fail(&format!("Failed to relay connection: {}", e)),
+    };
+
+    // Reading stdin blocks, so the process exits without waiting for it
+    thread::spawn(move || {
+        let _ = io::copy(&mut io::stdin(), &mut upload);
+        let _ = upload.shutdown(Shutdown::Write);
+    });
+
+    // Stdout is line buffered, so flush after every read
+    let mut download = stream;
+    let mut stdout = io::stdout();
+    let mut buffer = [0u8; 16 * 1024];
+    loop {
+        let read = match download.read(&mut buffer) {
+            Ok(0) => exit(0),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => fail(&format!("Connection failed: {}", e)),
+        };
+
+        if stdout
+            .write_all(&buffer[..read])
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            exit(0);
+        }
+    }
+}
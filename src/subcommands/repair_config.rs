@@ -1,4 +1,4 @@
-use crate::config::{Config, Profile};
+use crate::config::{Auth, Config, DynamicForward, Forward, Profile, SignOptions};
 use crate::util::fail;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -12,6 +12,10 @@ struct RepairableConfig {
     path: Option<String>,
     custom_ca: Option<String>,
     tls: Option<bool>,
+    namespace: Option<String>,
+    auth: Option<Auth>,
+    renew_threshold: Option<u64>,
+    cache_margin: Option<u64>,
     profiles: Option<HashMap<String, RepariableProfile>>,
 }
 
@@ -19,10 +23,20 @@ struct RepairableConfig {
 struct RepariableProfile {
     username: Option<String>,
     address: Option<String>,
+    port: Option<u16>,
+    proxy_jump: Option<String>,
+    jump: Option<Vec<String>>,
     role: Option<String>,
     private_key: Option<String>,
     public_key: Option<String>,
     options: Option<String>,
+    path: Option<String>,
+    namespace: Option<String>,
+    sign_options: Option<SignOptions>,
+    ephemeral: Option<bool>,
+    local_forwards: Option<Vec<Forward>>,
+    remote_forwards: Option<Vec<Forward>>,
+    dynamic_forward: Option<DynamicForward>,
 }
 
 pub fn repair_config(path: Option<String>) {
@@ -62,7 +76,11 @@ pub fn repair_config(path: Option<String>) {
         unrepaired_config.path.unwrap_or_else(|| String::from("ssh-ca")),
         unrepaired_config.custom_ca.unwrap_or_default(),
         unrepaired_config.tls.unwrap_or(true),
+        unrepaired_config.namespace,
+        unrepaired_config.auth,
     );
+    config.renew_threshold = unrepaired_config.renew_threshold;
+    config.cache_margin = unrepaired_config.cache_margin;
 
     // Attempt to repair profiles
     if let Some(profiles) = unrepaired_config.profiles {
@@ -72,10 +90,20 @@ pub fn repair_config(path: Option<String>) {
                 Profile {
                     username: profile.username.unwrap_or_else(whoami::username),
                     address: profile.address.unwrap_or_default(),
+                    port: profile.port,
+                    proxy_jump: profile.proxy_jump,
+                    jump: profile.jump.unwrap_or_default(),
                     role: profile.role.unwrap_or_default(),
                     private_key: profile.private_key,
                     public_key: profile.public_key,
                     options: profile.options.unwrap_or_default(),
+                    path: profile.path,
+                    namespace: profile.namespace,
+                    sign_options: profile.sign_options.unwrap_or_default(),
+                    ephemeral: profile.ephemeral.unwrap_or_default(),
+                    local_forwards: profile.local_forwards.unwrap_or_default(),
+                    remote_forwards: profile.remote_forwards.unwrap_or_default(),
+                    dynamic_forward: profile.dynamic_forward,
                 },
             );
         }
@@ -0,0 +1,3 @@
+mod trust_ca;
+
+pub use trust_ca::trust_ca;
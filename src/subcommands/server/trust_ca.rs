@@ -0,0 +1,284 @@
+use crate::api::ApiClient;
+use crate::sshd;
+use crate::util::fail;
+use similar::TextDiff;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// How the CA key is added to the file of CA keys sshd trusts
+#[derive(Debug, PartialEq)]
+enum CaKeys {
+    /// Add the key to the CA keys already trusted from the file
+    Append(String),
+    /// Make the key the only one in the file
+    Replace(String),
+}
+
+/// A file to be written along with its current contents
+struct Change {
+    path: PathBuf,
+    current: Option<String>,
+    updated: String,
+}
+
+pub async fn trust_ca(
+    client: &ApiClient,
+    ca_file: String,
+    sshd_config: String,
+    principals_dir: Option<String>,
+    principals: Vec<(String, String)>,
+    force: bool,
+    dry_run: bool,
+) {
+    if !principals.is_empty() && principals_dir.is_none() {
+        fail("Option '--principals-dir' is required when using '--principal'");
+    }
+
+    let key = match client.public_key().await {
+        Ok(key) => key,
+        Err(e) => fail(&format!("Failed to get CA public key: {}", e)),
+    };
+
+    let current = match read_to_string(&sshd_config) {
+        Ok(contents) => contents,
+        Err(e) => fail(&format!("Failed to read '{}': {}", sshd_config, e)),
+    };
+
+    let configured = sshd::get_directive(&current, "TrustedUserCAKeys");
+    let (ca_file, ca_change) = match plan_ca_keys(configured.as_deref(), &ca_file, force) {
+        CaKeys::Append(trusted) => {
+            let ca_change = append(Path::new(&trusted), &key);
+            if trusted != ca_file && ca_change.current.as_ref() != Some(&ca_change.updated) {
+                leg::warn(
+                    &format!(
+                        "sshd already trusts the CA keys in '{}', adding the CA key to them. Use '--force' to trust only '{}' instead",
+                        trusted, ca_file
+                    ),
+                    None,
+                    None,
+                );
+            }
+            (trusted, ca_change)
+        }
+        CaKeys::Replace(ca_file) => {
+            let ca_change = change(Path::new(&ca_file), format!("{}\n", key));
+
+            // Only forcing replaces CA keys that sshd trusts
+            match configured {
+                Some(trusted) if !force || trusted.eq_ignore_ascii_case("none") => {}
+                Some(trusted) if trusted != ca_file => leg::warn(
+                    &format!("No longer trusting the CA keys in '{}'", trusted),
+                    None,
+                    None,
+                ),
+                Some(trusted) if ca_change.current.as_ref() != Some(&ca_change.updated) => {
+                    leg::warn(
+                        &format!("Replacing the CA keys trusted in '{}'", trusted),
+                        None,
+                        None,
+                    )
+                }
+                _ => {}
+            }
+            (ca_file, ca_change)
+        }
+    };
+
+    // The CA key is written first so sshd never references a missing file
+    let mut changes = vec![ca_change];
+
+    // One file per user listing the principals they accept
+    if let Some(directory) = &principals_dir {
+        for (user, accepted) in &principals {
+            if user.is_empty() || user.contains('/') {
+                fail(&format!("Invalid user '{}'", user));
+            }
+
+            let mut contents = accepted
+                .split(',')
+                .map(str::trim)
+                .filter(|principal| !principal.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            contents.push('\n');
+            changes.push(change(&Path::new(directory).join(user), contents));
+        }
+    }
+
+    // Point sshd at the CA key and principals
+    let mut updated = sshd::set_directive(&current, "TrustedUserCAKeys", &ca_file);
+    if let Some(directory) = &principals_dir {
+        let pattern = Path::new(directory).join("%u");
+        updated = sshd::set_directive(
+            &updated,
+            "AuthorizedPrincipalsFile",
+            &pattern.display().to_string(),
+        );
+    }
+    changes.push(Change {
+        path: PathBuf::from(&sshd_config),
+        current: Some(current),
+        updated,
+    });
+
+    let changes = changes
+        .into_iter()
+        .filter(|change| change.current.as_ref() != Some(&change.updated))
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        leg::info("sshd already trusts the CA", None, None);
+        return;
+    }
+
+    // Only show what would change
+    if dry_run {
+        for change in &changes {
+            let path = change.path.display().to_string();
+            let current = change.current.clone().unwrap_or_default();
+            let diff = TextDiff::from_lines(&current, &change.updated);
+            print!(
+                "{}",
+                diff.unified_diff().header(
+                    if change.current.is_some() {
+                        &path
+                    } else {
+                        "/dev/null"
+                    },
+                    &path
+                )
+            );
+        }
+        return;
+    }
+
+    for change in &changes {
+        if let Some(parent) = change.path.parent() {
+            if let Err(e) = create_dir_all(parent) {
+                fail(&format!("Failed to create '{}': {}", parent.display(), e));
+            }
+        }
+
+        match write(&change.path, &change.updated) {
+            Ok(_) => leg::success(&format!("Wrote: {}", change.path.display()), None, None),
+            Err(e) => fail(&format!(
+                "Failed to write '{}': {}",
+                change.path.display(),
+                e
+            )),
+        }
+    }
+
+    leg::info("Reload sshd to apply the changes", None, None);
+}
+
+/// Decide which file of CA keys the CA key goes into, given the file sshd_config already
+/// trusts. sshd only reads one such file, so any trusted keys are kept unless forced.
+/// A file sshd does not trust yet is replaced so that no stale keys become trusted.
+fn plan_ca_keys(configured: Option<&str>, ca_file: &str, force: bool) -> CaKeys {
+    match configured {
+        Some(trusted) if !force && !trusted.eq_ignore_ascii_case("none") => {
+            CaKeys::Append(trusted.to_string())
+        }
+        _ => CaKeys::Replace(ca_file.to_string()),
+    }
+}
+
+/// Plan to add a line to a file that may not exist yet, unless it already has the line
+fn append(path: &Path, line: &str) -> Change {
+    let mut change = change(path, String::new());
+    let mut updated = change.current.clone().unwrap_or_default();
+    if !updated
+        .lines()
+        .any(|existing| existing.trim() == line.trim())
+    {
+        if !updated.is_empty() && !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        updated.push_str(line);
+        updated.push('\n');
+    }
+
+    change.updated = updated;
+    change
+}
+
+/// Plan to replace the contents of a file that may not exist yet
+fn change(path: &Path, updated: String) -> Change {
+    let current = match read_to_string(path) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => fail(&format!("Failed to read '{}': {}", path.display(), e)),
+    };
+
+    Change {
+        path: path.to_path_buf(),
+        current,
+        updated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA_FILE: &str = "/etc/ssh/trusted-user-ca-keys.pem";
+
+    #[test]
+    fn appends_to_the_configured_ca_file() {
+        assert_eq!(
+            plan_ca_keys(Some(CA_FILE), CA_FILE, false),
+            CaKeys::Append(String::from(CA_FILE))
+        );
+        assert_eq!(
+            plan_ca_keys(Some(CA_FILE), CA_FILE, true),
+            CaKeys::Replace(String::from(CA_FILE))
+        );
+    }
+
+    #[test]
+    fn appends_to_another_configured_file() {
+        let other = "/etc/ssh/other-ca-keys.pem";
+        assert_eq!(
+            plan_ca_keys(Some(other), CA_FILE, false),
+            CaKeys::Append(String::from(other))
+        );
+        assert_eq!(
+            plan_ca_keys(Some(other), CA_FILE, true),
+            CaKeys::Replace(String::from(CA_FILE))
+        );
+    }
+
+    #[test]
+    fn replaces_an_untrusted_ca_file() {
+        for configured in &[None, Some("none"), Some("NONE")] {
+            for force in &[false, true] {
+                assert_eq!(
+                    plan_ca_keys(*configured, CA_FILE, *force),
+                    CaKeys::Replace(String::from(CA_FILE))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn appends_keys_once() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("ca.pem");
+
+        let created = append(&path, "ssh-ed25519 AAAA vault");
+        assert_eq!(created.current, None);
+        assert_eq!(created.updated, "ssh-ed25519 AAAA vault\n");
+
+        write(&path, "ssh-rsa BBBB other").unwrap();
+        let appended = append(&path, "ssh-ed25519 AAAA vault");
+        assert_eq!(
+            appended.updated,
+            "ssh-rsa BBBB other\nssh-ed25519 AAAA vault\n"
+        );
+
+        write(&path, &appended.updated).unwrap();
+        let unchanged = append(&path, "ssh-ed25519 AAAA vault");
+        assert_eq!(unchanged.current.as_ref(), Some(&unchanged.updated));
+    }
+}
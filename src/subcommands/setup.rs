@@ -1,36 +1,85 @@
-use crate::config::Config;
+use crate::api::ApiClient;
+use crate::auth;
+use crate::config::{Auth, Config};
+use crate::environment::{helper_token, VaultEnvironment};
+use crate::errors::ApiError;
 use crate::util::fail;
 use std::io::{self, BufRead, Write};
 
-pub fn interactive() {
+pub async fn interactive() {
+    // Offer to import the settings of the Vault CLI
+    let environment = VaultEnvironment::load();
+    let helper_token = helper_token();
+    let (environment, helper_token) = if (!environment.is_empty() || helper_token.is_some())
+        && prompt_bool(
+            "Found settings for the Vault CLI, do you want to import them?",
+            true,
+        ) {
+        (environment, helper_token)
+    } else {
+        (VaultEnvironment::default(), None)
+    };
+
     // Get the server address
-    let server = prompt_default(
-        "What server should vssh connect to?",
-        String::from("https://127.0.0.1:8200"),
-    );
+    let server = match environment.address.clone() {
+        Some(address) => address,
+        None => prompt_default(
+            "What server should vssh connect to?",
+            String::from("https://127.0.0.1:8200"),
+        ),
+    };
 
     // Check if using HTTPS
-    let using_https = prompt_bool("Are you using HTTPS to connect to the server?", true);
+    let using_https = if server.starts_with("https://") {
+        true
+    } else if server.starts_with("http://") {
+        false
+    } else {
+        prompt_bool("Are you using HTTPS to connect to the server?", true)
+    };
 
     // Options only for those using HTTPS
     let custom_ca: String;
     let tls = if using_https {
         // Ask if using custom CA and get certificate path
-        custom_ca = if prompt_bool("Are you using a custom certificate authority?", false) {
+        custom_ca = if let Some(ca_cert) = environment.ca_cert.clone() {
+            ca_cert
+        } else if prompt_bool("Are you using a custom certificate authority?", false) {
             prompt("What is the path to your CA's public key?")
         } else {
             String::from("")
         };
 
         // Prompt for TLS verification
-        prompt_bool("Do you want to verify TLS certificates when connecting to the server? Answer no if you are using a self-signed certificate.", true)
+        match environment.skip_verify {
+            Some(skip_verify) => !skip_verify,
+            None => prompt_bool("Do you want to verify TLS certificates when connecting to the server? Answer no if you are using a self-signed certificate.", true),
+        }
     } else {
         custom_ca = String::from("");
         true
     };
 
-    // Get the authentication token
-    let token = prompt("What token to vssh authenticated to the server with?");
+    // Get the authentication method and its credentials
+    let (token, auth) = if let Some(token) = environment.token.clone().or(helper_token) {
+        leg::info("Using the token from the Vault CLI", None, None);
+        (token, None)
+    } else {
+        let method = prompt_default(
+            "How should vssh authenticate to the server? (token/approle/userpass/ldap/oidc)",
+            String::from("token"),
+        );
+        match method.as_str() {
+            "token" => (
+                prompt("What token to vssh authenticated to the server with?"),
+                None,
+            ),
+            "approle" => (String::new(), Some(prompt_approle())),
+            "userpass" | "ldap" => (String::new(), Some(prompt_password_method(&method))),
+            "oidc" => (String::new(), Some(prompt_oidc())),
+            _ => fail(&format!("Unsupported authentication method '{}'", method)),
+        }
+    };
 
     // Get the path for the SSH secret engine
     let path = prompt_default(
@@ -38,13 +87,25 @@ pub fn interactive() {
         String::from("ssh-ca"),
     );
 
+    // Get the namespace for Vault Enterprise
+    let namespace = match environment.namespace.clone() {
+        Some(namespace) => Some(namespace),
+        None => Some(prompt(
+            "What namespace should vssh use? Leave empty if not using Vault Enterprise namespaces.",
+        ))
+        .filter(|namespace| !namespace.is_empty()),
+    };
+
     // Ensure the configuration is valid
-    let config = Config::new(server, token, path, custom_ca, tls);
+    let config = Config::new(server, token, path, custom_ca, tls, namespace, auth);
     match config.validate() {
         Ok(_) => {}
         Err(e) => fail(&format!("Invalid configuration: {}", e)),
     }
 
+    // Ensure the server accepts the configuration
+    verify(&config, &environment).await;
+
     // Write the configuration to disk
     match config.write() {
         Ok(_) => leg::success("Successfully configured", None, None),
@@ -52,23 +113,95 @@ pub fn interactive() {
     }
 }
 
-pub fn noninteractive(server: String, tls: bool, token: String, path: String, custom_ca: String) {
+#[allow(clippy::too_many_arguments)]
+pub async fn noninteractive(
+    mut server: String,
+    mut tls: bool,
+    mut token: String,
+    path: String,
+    mut custom_ca: String,
+    mut namespace: Option<String>,
+    auth_method: String,
+    auth_mount: Option<String>,
+    role_id: String,
+    secret_id_file: Option<String>,
+    secret_id_env: Option<String>,
+    username: Option<String>,
+    oidc_role: Option<String>,
+    callback_port: Option<u16>,
+    import_environment: bool,
+) {
+    // Fill in missing values from the Vault CLI settings
+    let environment = if import_environment {
+        VaultEnvironment::load()
+    } else {
+        VaultEnvironment::default()
+    };
+    if server.is_empty() {
+        server = environment.address.clone().unwrap_or_default();
+    }
+    if token.is_empty() && auth_method == "token" && import_environment {
+        token = environment
+            .token
+            .clone()
+            .or_else(helper_token)
+            .unwrap_or_default();
+    }
+    if custom_ca.is_empty() {
+        custom_ca = environment.ca_cert.clone().unwrap_or_default();
+    }
+    if environment.skip_verify == Some(true) {
+        tls = false;
+    }
+    if namespace.is_none() {
+        namespace = environment.namespace.clone();
+    }
+
     // Ensure each parameter exists
-    if server == "" {
+    if server.is_empty() {
         fail("Option '--server' is required when running non-interactively");
-    } else if token == "" {
+    } else if token.is_empty() && auth_method == "token" {
         fail("Option '--token' is required when running non-interactively");
-    } else if path == "" {
+    } else if role_id.is_empty() && auth_method == "approle" {
+        fail("Option '--role-id' is required when using AppRole");
+    } else if path.is_empty() {
         fail("Option '--path' is required when running non-interactively");
     }
 
+    // Build the authentication method
+    let auth = match auth_method.as_str() {
+        "approle" => Some(Auth::AppRole {
+            mount: auth_mount.unwrap_or_else(|| String::from("approle")),
+            role_id,
+            secret_id_file,
+            secret_id_env,
+        }),
+        "userpass" => Some(Auth::Userpass {
+            mount: auth_mount.unwrap_or_else(|| String::from("userpass")),
+            username: username.unwrap_or_else(whoami::username),
+        }),
+        "ldap" => Some(Auth::Ldap {
+            mount: auth_mount.unwrap_or_else(|| String::from("ldap")),
+            username: username.unwrap_or_else(whoami::username),
+        }),
+        "oidc" => Some(Auth::Oidc {
+            mount: auth_mount.unwrap_or_else(|| String::from("oidc")),
+            role: oidc_role,
+            port: callback_port.unwrap_or(auth::DEFAULT_CALLBACK_PORT),
+        }),
+        _ => None,
+    };
+
     // Ensure the configuration is valid
-    let config = Config::new(server, token, path, custom_ca, tls);
+    let config = Config::new(server, token, path, custom_ca, tls, namespace, auth);
     match config.validate() {
         Ok(_) => {}
         Err(e) => fail(&format!("Invalid configuration: {}", e)),
     }
 
+    // Ensure the server accepts the configuration
+    verify(&config, &environment).await;
+
     // Write the configuration to disk
     match config.write() {
         Ok(_) => leg::success("Successfully configured", None, None),
@@ -76,6 +209,99 @@ pub fn noninteractive(server: String, tls: bool, token: String, path: String, cu
     }
 }
 
+/// Prompt for the details needed to log in with an AppRole
+fn prompt_approle() -> Auth {
+    let mount = prompt_default(
+        "What path is the AppRole auth method mounted at?",
+        String::from("approle"),
+    );
+    let role_id = prompt("What role ID should vssh log in with?");
+
+    // The secret ID is never stored in the configuration
+    let (secret_id_file, secret_id_env) = if prompt_bool("Is the secret ID stored in a file?", true)
+    {
+        (Some(prompt("What file contains the secret ID?")), None)
+    } else {
+        (
+            None,
+            Some(prompt_default(
+                "What environment variable contains the secret ID?",
+                String::from("VAULT_SECRET_ID"),
+            )),
+        )
+    };
+
+    Auth::AppRole {
+        mount,
+        role_id,
+        secret_id_file,
+        secret_id_env,
+    }
+}
+
+/// Prompt for the details needed to log in with a username and password.
+/// The password itself is requested whenever a login is required.
+fn prompt_password_method(method: &str) -> Auth {
+    let mount = prompt_default(
+        "What path is the auth method mounted at?",
+        method.to_string(),
+    );
+    let username = prompt_default("What username should vssh log in as?", whoami::username());
+
+    if method == "ldap" {
+        Auth::Ldap { mount, username }
+    } else {
+        Auth::Userpass { mount, username }
+    }
+}
+
+/// Prompt for the details needed to log in through OIDC
+fn prompt_oidc() -> Auth {
+    let mount = prompt_default(
+        "What path is the OIDC auth method mounted at?",
+        String::from("oidc"),
+    );
+    let role = prompt("What OIDC role should vssh log in with? Leave empty to use the default.");
+    let port = prompt_default(
+        "What port should vssh listen on for the OIDC callback?",
+        auth::DEFAULT_CALLBACK_PORT.to_string(),
+    );
+
+    Auth::Oidc {
+        mount,
+        role: if role.is_empty() { None } else { Some(role) },
+        port: match port.parse() {
+            Ok(port) => port,
+            Err(_) => fail(&format!("Invalid port '{}'", port)),
+        },
+    }
+}
+
+/// Ensure the credentials are accepted and an SSH secrets engine is mounted at the configured path.
+/// Tokens without access to the list of mounts only produce a warning.
+/// Only the Vault CLI settings that were imported may override the configuration.
+async fn verify(config: &Config, environment: &VaultEnvironment) {
+    let mut client = ApiClient::from_config(config, environment);
+
+    // Obtain a token if not using a static one
+    if let Some(auth) = &config.auth {
+        match auth::login(&mut client, auth, false).await {
+            Ok(_) => leg::success("Logged in to the server", None, None),
+            Err(e) => fail(&format!("Failed to authenticate: {}", e)),
+        }
+    }
+
+    match client.validate_mount().await {
+        Ok(_) => leg::success("Found SSH CA on the server", None, None),
+        Err(ApiError::PermissionDenied) => leg::warn(
+            "Unable to verify the SSH CA path, token cannot read sys/mounts",
+            None,
+            None,
+        ),
+        Err(e) => fail(&format!("Invalid SSH CA path: {}", e)),
+    }
+}
+
 /// Read a line from stdin with a given prompt.
 fn prompt(prompt: &'static str) -> String {
     // Display the prompt
@@ -97,7 +323,7 @@ fn prompt_default(prompt: &'static str, default: String) -> String {
     let line = read_line();
 
     // Use default if empty
-    if line == "" {
+    if line.is_empty() {
         default
     } else {
         line
@@ -105,7 +331,7 @@ fn prompt_default(prompt: &'static str, default: String) -> String {
 }
 
 /// Read a boolean with a yes or no prompt from stdin.
-fn prompt_bool(prompt: &'static str, default: bool) -> bool {
+pub fn prompt_bool(prompt: &'static str, default: bool) -> bool {
     // Display prompt
     if default {
         print!("{} [Y/n] ", prompt);
@@ -1,9 +1,17 @@
 use crate::api::ApiClient;
+use crate::config::SignOptions;
+use crate::subcommands::cert::summarize;
 use crate::util::fail;
 use std::fs::{canonicalize, read_to_string, OpenOptions};
 use std::io::{ErrorKind, Write};
 
-pub async fn sign<'a>(client: &ApiClient, role: String, key: String, output: String) {
+pub async fn sign(
+    client: &ApiClient,
+    role: String,
+    key: String,
+    output: String,
+    options: SignOptions,
+) {
     // Convert relative to absolute path and ensure exists
     let path = match canonicalize(&key) {
         Ok(path) => path,
@@ -23,20 +31,27 @@ pub async fn sign<'a>(client: &ApiClient, role: String, key: String, output: Str
     };
 
     // Sign the public key
-    let signed = match client.sign(role.to_string(), contents).await {
+    let signed = match client.sign(role.to_string(), contents, &options).await {
         Ok(signed) => signed,
         Err(e) => fail(&format!("Failed to sign public key: {}", e)),
     };
 
     leg::success("Signed public key with role", None, None);
+    summarize(&signed);
 
     // Output to stdout if no file
-    if output == "" {
+    if output.is_empty() {
         println!("{}", signed);
+        return;
     }
 
     // Create output file if not exists
-    let mut file = match OpenOptions::new().write(true).create(true).open(&output) {
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&output)
+    {
         Ok(file) => file,
         Err(e) => {
             match e.kind() {
@@ -0,0 +1,353 @@
+use crate::config::{Config, Profile};
+use crate::ssh_config::glob_matches;
+use crate::util::fail;
+use similar::TextDiff;
+use std::fs::{create_dir_all, read_to_string, set_permissions, write, Permissions};
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Directory relative includes of the system configuration are resolved against
+const SYSTEM_DIR: &str = "/etc/ssh";
+
+/// Comment at the top of the generated file
+const HEADER: &str = "# Generated by vssh from its profiles, changes will be overwritten.
+# Regenerate with `vssh ssh-config generate`.
+";
+
+pub fn generate(
+    config: &Config,
+    output: Option<String>,
+    ssh_config: Option<String>,
+    no_include: bool,
+    dry_run: bool,
+) {
+    let mut ssh_dir = dirs::home_dir().expect("Failed to retrieve user's home directory");
+    ssh_dir.push(".ssh");
+
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        None => ssh_dir.join("config.d").join("vssh"),
+    };
+    let ssh_config = match ssh_config {
+        Some(ssh_config) => PathBuf::from(ssh_config),
+        None => ssh_dir.join("config"),
+    };
+
+    // OpenSSH resolves relative includes of the system configuration against its directory
+    let include_dir = if ssh_config.starts_with(SYSTEM_DIR) {
+        PathBuf::from(SYSTEM_DIR)
+    } else {
+        ssh_dir.clone()
+    };
+
+    // Write the Host blocks
+    let rendered = render(config);
+    let current = read(&output);
+    if current.as_ref() == Some(&rendered) {
+        leg::info(
+            &format!("'{}' is already up to date", output.display()),
+            None,
+            None,
+        );
+    } else if dry_run {
+        print_diff(&output, current.as_deref(), &rendered);
+    } else {
+        create_parent(&output);
+        match write(&output, &rendered) {
+            Ok(_) => leg::success(
+                &format!(
+                    "Wrote {} profiles to: {}",
+                    config.profiles.len(),
+                    output.display()
+                ),
+                None,
+                None,
+            ),
+            Err(e) => fail(&format!("Failed to write '{}': {}", output.display(), e)),
+        }
+    }
+
+    // Ensure ssh reads the file
+    let contents = read(&ssh_config).unwrap_or_default();
+    match find_include(&contents, &include_dir, &output) {
+        Some(true) => return,
+        Some(false) => {
+            leg::warn(
+                &format!(
+                    "'{}' is only included within a Host or Match block of '{}'",
+                    output.display(),
+                    ssh_config.display()
+                ),
+                None,
+                None,
+            );
+            return;
+        }
+        None => {}
+    }
+
+    // Paths in the directory includes are resolved against are written relative to it
+    let line = format!(
+        "Include {}\n",
+        quote(
+            &output
+                .strip_prefix(&include_dir)
+                .unwrap_or(&output)
+                .display()
+                .to_string()
+        )
+    );
+    if no_include {
+        leg::warn(
+            &format!(
+                "'{}' is not included, add `{}` to the top of '{}'",
+                output.display(),
+                line.trim_end(),
+                ssh_config.display()
+            ),
+            None,
+            None,
+        );
+        return;
+    }
+
+    // Includes must come before any Host block to apply to every host
+    let updated = if contents.is_empty() {
+        line
+    } else {
+        format!("{}\n{}", line, contents)
+    };
+    if dry_run {
+        let current = read(&ssh_config);
+        print_diff(&ssh_config, current.as_deref(), &updated);
+        return;
+    }
+
+    create_parent(&ssh_config);
+    match write(&ssh_config, updated) {
+        Ok(_) => leg::success(
+            &format!("Included the profiles from: {}", ssh_config.display()),
+            None,
+            None,
+        ),
+        Err(e) => fail(&format!(
+            "Failed to write '{}': {}",
+            ssh_config.display(),
+            e
+        )),
+    }
+}
+
+/// Render a Host block for every profile, ordered by name
+fn render(config: &Config) -> String {
+    let mut profiles = config.profiles.iter().collect::<Vec<_>>();
+    profiles.sort_by_key(|(name, _)| name.as_str());
+
+    let mut rendered = String::from(HEADER);
+    for (name, profile) in profiles {
+        rendered.push('\n');
+        rendered.push_str(&format!("Host {}\n", quote(name)));
+        for (keyword, value) in directives(profile) {
+            rendered.push_str(&format!("    {} {}\n", keyword, quote(&value)));
+        }
+    }
+    rendered
+}
+
+/// The client configuration for connecting to a profile
+fn directives(profile: &Profile) -> Vec<(&'static str, String)> {
+    let mut directives = vec![
+        ("HostName", profile.address.clone()),
+        ("User", profile.username.clone()),
+    ];
+    if let Some(port) = profile.port {
+        directives.push(("Port", port.to_string()));
+    }
+
+    // Ephemeral keys only exist while connecting through vssh
+    if !profile.ephemeral {
+        let private_key = profile
+            .private_key
+            .clone()
+            .unwrap_or_else(|| String::from("~/.ssh/id_rsa"));
+        directives.push(("CertificateFile", format!("{}-cert.pub", private_key)));
+        directives.push(("IdentityFile", private_key));
+    }
+
+    // Jump hosts are profiles, so ssh finds their settings in their own Host blocks
+    if !profile.jump.is_empty() {
+        directives.push(("ProxyJump", profile.jump.join(",")));
+    } else if let Some(proxy_jump) = &profile.proxy_jump {
+        directives.push(("ProxyJump", proxy_jump.clone()));
+    }
+    directives
+}
+
+/// Quote a value containing whitespace so ssh reads it as one argument.
+/// Not every version of OpenSSH can escape double quotes, so values cannot contain them.
+fn quote(value: &str) -> String {
+    if value.contains('"') {
+        fail(&format!(
+            "Cannot write '{}' to the ssh configuration since it contains a double quote",
+            value
+        ));
+    }
+
+    if value.contains(char::is_whitespace) {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Find an Include line for the file, returning whether it applies to every host.
+/// Relative paths are resolved against the directory of the configuration.
+fn find_include(contents: &str, include_dir: &Path, output: &Path) -> Option<bool> {
+    let home = dirs::home_dir().expect("Failed to retrieve user's home directory");
+    let mut global = true;
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+
+        if keyword.eq_ignore_ascii_case("host") || keyword.eq_ignore_ascii_case("match") {
+            global = false;
+        } else if keyword.eq_ignore_ascii_case("include") {
+            let included = parts.any(|pattern| {
+                let pattern = pattern.trim_matches('"');
+                let pattern = match pattern.strip_prefix("~/") {
+                    Some(relative) => home.join(relative),
+                    None => include_dir.join(pattern),
+                };
+                glob_matches(
+                    &pattern.display().to_string(),
+                    &output.display().to_string(),
+                )
+            });
+            if included {
+                return Some(global);
+            }
+        }
+    }
+    None
+}
+
+/// Read a file that may not exist yet
+fn read(path: &Path) -> Option<String> {
+    match read_to_string(path) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => fail(&format!("Failed to read '{}': {}", path.display(), e)),
+    }
+}
+
+/// Create the directory of a file with the same permissions as OpenSSH
+fn create_parent(path: &Path) {
+    if let Some(parent) = path.parent() {
+        if !parent.exists() {
+            if let Err(e) = create_dir_all(parent)
+                .and_then(|_| set_permissions(parent, Permissions::from_mode(0o700)))
+            {
+                fail(&format!("Failed to create '{}': {}", parent.display(), e));
+            }
+        }
+    }
+}
+
+/// Print the changes to a file as a unified diff
+fn print_diff(path: &Path, current: Option<&str>, updated: &str) {
+    let path = path.display().to_string();
+    let diff = TextDiff::from_lines(current.unwrap_or_default(), updated);
+    print!(
+        "{}",
+        diff.unified_diff().header(
+            if current.is_some() {
+                &path
+            } else {
+                "/dev/null"
+            },
+            &path
+        )
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_whitespace() {
+        assert_eq!(quote("web"), "web");
+        assert_eq!(quote("/home/a b/.ssh/id"), "\"/home/a b/.ssh/id\"");
+        assert_eq!(quote("tab\tseparated"), "\"tab\tseparated\"");
+    }
+
+    #[test]
+    fn finds_relative_include_in_user_config() {
+        let ssh_dir = Path::new("/home/alice/.ssh");
+        let output = ssh_dir.join("config.d/vssh");
+
+        assert_eq!(
+            find_include("Include config.d/*\n", ssh_dir, &output),
+            Some(true)
+        );
+        assert_eq!(
+            find_include("include \"config.d/vssh\"\n", ssh_dir, &output),
+            Some(true)
+        );
+        assert_eq!(find_include("Include other/*\n", ssh_dir, &output), None);
+    }
+
+    #[test]
+    fn finds_relative_include_in_system_config() {
+        let output = Path::new("/etc/ssh/ssh_config.d/vssh");
+        assert_eq!(
+            find_include(
+                "Include ssh_config.d/*.conf ssh_config.d/vssh\n",
+                Path::new(SYSTEM_DIR),
+                output
+            ),
+            Some(true)
+        );
+
+        // The same include in a user configuration refers to ~/.ssh instead
+        assert_eq!(
+            find_include(
+                "Include ssh_config.d/vssh\n",
+                Path::new("/home/alice/.ssh"),
+                output
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_include_within_host_block() {
+        let ssh_dir = Path::new("/home/alice/.ssh");
+        let contents = "# Include config.d/vssh\nHost web\n    Include config.d/vssh\n";
+        assert_eq!(
+            find_include(contents, ssh_dir, &ssh_dir.join("config.d/vssh")),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn finds_absolute_and_home_includes() {
+        let home = dirs::home_dir().unwrap();
+        let output = home.join(".ssh/config.d/vssh");
+        let ssh_dir = home.join(".ssh");
+
+        assert_eq!(
+            find_include("Include ~/.ssh/config.d/*\n", &ssh_dir, &output),
+            Some(true)
+        );
+        let absolute = format!("Include {}\n", output.display());
+        assert_eq!(
+            find_include(&absolute, Path::new(SYSTEM_DIR), &output),
+            Some(true)
+        );
+    }
+}
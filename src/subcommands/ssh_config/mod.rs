@@ -0,0 +1,3 @@
+mod generate;
+
+pub use generate::generate;
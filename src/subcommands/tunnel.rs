@@ -0,0 +1,272 @@
+use crate::api::ApiClient;
+use crate::cache::DEFAULT_CACHE_MARGIN;
+use crate::config::{Config, Profile};
+use crate::errors::SignError;
+use crate::subcommands::clients::Clients;
+use crate::subcommands::connect::{private_dir, sign_profile, write_identity, Key, Transport};
+use crate::subcommands::profiles::{jump_hosts, profile_options, JumpHosts};
+use crate::util::{fail, format_duration};
+use std::future::Future;
+use std::os::unix::process::ExitStatusExt;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Child;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::time::delay_for;
+
+/// Longest wait in seconds between attempts to reconnect
+const MAX_RETRY_DELAY: u64 = 300;
+
+/// Connections lasting this long reset the wait between attempts to reconnect
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+/// Options to only forward ports and notice when the connection drops
+const TUNNEL_OPTIONS: &[&str] = &[
+    "-N",
+    "-o",
+    "ExitOnForwardFailure=yes",
+    "-o",
+    "ServerAliveInterval=15",
+    "-o",
+    "ServerAliveCountMax=3",
+];
+
+/// How a connection of the tunnel ended
+enum Ended {
+    /// ssh exited with the status, or could not be started
+    Exited(Option<i32>),
+    /// vssh was interrupted or terminated
+    Stopped,
+}
+
+pub async fn tunnel<F, Fut>(
+    config: &Config,
+    name: String,
+    path: Option<String>,
+    namespace: Option<String>,
+    retry_delay: u64,
+    authenticate: F,
+) where
+    F: Fn(Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<ApiClient, SignError>>,
+{
+    if retry_delay == 0 {
+        fail("The retry delay must be at least 1 second");
+    }
+
+    // Ensure profile exists
+    let profile = match config.profiles.get(&name) {
+        Some(profile) => profile,
+        None => fail(&format!("Profile '{}' does not exist", name)),
+    };
+    leg::success("Retrieved selected profile", None, None);
+
+    let forwards = forward_options(profile);
+    if forwards.is_empty() {
+        fail(&format!(
+            "Profile '{}' has no forwards, add them with 'vssh profiles update'",
+            name
+        ));
+    }
+
+    // Handling the signals keeps them from stopping vssh before it cleans up
+    let mut interrupt = signal(SignalKind::interrupt()).expect("Failed to listen for signals");
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for signals");
+
+    let cache_margin = config.cache_margin.unwrap_or(DEFAULT_CACHE_MARGIN);
+    let path = path.or_else(|| profile.path.clone());
+    let namespace = namespace.or_else(|| profile.namespace.clone());
+    let mut delay = retry_delay;
+    let mut reconnecting = false;
+    loop {
+        // Vault may be unreachable for a while when reconnecting, which is retried like a
+        // dropped connection. Failing to sign for the first connection is likely a mistake.
+        let signed = sign(
+            config,
+            &name,
+            profile,
+            path.clone(),
+            namespace.clone(),
+            cache_margin,
+            &authenticate,
+        )
+        .await;
+        let (jump_hosts, key, certificate) = match signed {
+            Ok(signed) => signed,
+            Err(e) if !reconnecting => fail(&e.to_string()),
+            Err(e) => {
+                leg::warn(
+                    &format!("{}, retrying in {}", e, format_duration(delay)),
+                    None,
+                    None,
+                );
+                if stopped_during(delay, &mut interrupt, &mut terminate).await {
+                    break;
+                }
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+                continue;
+            }
+        };
+
+        reconnecting = true;
+
+        // Only write the certificates once everything is signed
+        let directory = private_dir();
+        let identity = match write_identity(directory.path(), "id", &key, &certificate) {
+            Ok(identity) => identity,
+            Err(e) => {
+                drop(directory);
+                fail(&format!("Failed to write certificate: {}", e));
+            }
+        };
+        let chain = match jump_hosts.as_ref().map(JumpHosts::write).transpose() {
+            Ok(chain) => chain,
+            Err(e) => {
+                drop(directory);
+                fail(&format!("Failed to write jump host certificates: {}", e));
+            }
+        };
+
+        let mut options = profile_options(profile, Transport::Ssh);
+        if let Some(chain) = &chain {
+            options.extend(chain.options(profile));
+        }
+        options.extend(forwards.iter().cloned());
+
+        let mut command = Transport::Ssh.command(&identity, &options);
+        command
+            .arg(format!("{}@{}", profile.username, profile.address))
+            .stdin(Stdio::null());
+
+        let started = Instant::now();
+        let ended = match command.spawn() {
+            Ok(child) => {
+                leg::wait("Tunnel open", None, None);
+                wait(child, &mut interrupt, &mut terminate).await
+            }
+            Err(e) => {
+                leg::error(&format!("Failed to start ssh command: {}", e), None, None);
+                Ended::Exited(None)
+            }
+        };
+
+        // Clean up before reconnecting so that nothing is left behind
+        if let Err(e) = directory.close() {
+            leg::error(
+                &format!("Failed to remove temporary certificate: {}", e),
+                None,
+                None,
+            );
+        }
+        if let Some(chain) = chain {
+            chain.close();
+        }
+
+        match ended {
+            Ended::Stopped => break,
+            Ended::Exited(status) => {
+                if started.elapsed() >= STABLE_CONNECTION {
+                    delay = retry_delay;
+                }
+                leg::warn(
+                    &format!(
+                        "Tunnel closed{}, reconnecting in {}",
+                        status.map_or_else(String::new, |code| format!(" with status {}", code)),
+                        format_duration(delay)
+                    ),
+                    None,
+                    None,
+                );
+
+                if stopped_during(delay, &mut interrupt, &mut terminate).await {
+                    break;
+                }
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    }
+
+    leg::success("Closed tunnel", None, None);
+}
+
+/// Sign certificates for the jump hosts and the profile, reusing cached ones that are not
+/// about to expire. Authenticates for every connection since the token may have expired
+/// in the meantime.
+async fn sign<'a, F, Fut>(
+    config: &'a Config,
+    name: &str,
+    profile: &'a Profile,
+    path: Option<String>,
+    namespace: Option<String>,
+    cache_margin: u64,
+    authenticate: &F,
+) -> Result<(Option<JumpHosts<'a>>, Key, String), SignError>
+where
+    F: Fn(Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<ApiClient, SignError>>,
+{
+    let mut clients = Clients::new(authenticate);
+    let jump_hosts = jump_hosts(config, name, profile, &mut clients, cache_margin).await?;
+    let client = clients.get(path, namespace).await?;
+    let (key, certificate) = sign_profile(client, profile, cache_margin).await?;
+    Ok((jump_hosts, key, certificate))
+}
+
+/// The ssh options that open the forwards of a profile
+fn forward_options(profile: &Profile) -> Vec<String> {
+    let mut options = Vec::new();
+    for forward in &profile.local_forwards {
+        leg::info(&format!("Local forward: {}", forward), None, None);
+        options.push(String::from("-L"));
+        options.push(forward.to_string());
+    }
+    for forward in &profile.remote_forwards {
+        leg::info(&format!("Remote forward: {}", forward), None, None);
+        options.push(String::from("-R"));
+        options.push(forward.to_string());
+    }
+    if let Some(forward) = &profile.dynamic_forward {
+        leg::info(&format!("SOCKS proxy: {}", forward), None, None);
+        options.push(String::from("-D"));
+        options.push(forward.to_string());
+    }
+
+    if !options.is_empty() {
+        options.extend(TUNNEL_OPTIONS.iter().map(|option| option.to_string()));
+    }
+    options
+}
+
+/// Wait for ssh to exit, stopping it if vssh is interrupted or terminated.
+/// Expiring certificates do not end established connections, so they are only
+/// renewed once ssh has to reconnect.
+async fn wait(mut child: Child, interrupt: &mut Signal, terminate: &mut Signal) -> Ended {
+    let (ended, forwarded) = tokio::select! {
+        status = &mut child => {
+            return Ended::Exited(status.ok().map(|status| {
+                status
+                    .code()
+                    .or_else(|| status.signal().map(|signal| 128 + signal))
+                    .unwrap_or(1)
+            }))
+        }
+        _ = interrupt.recv() => (Ended::Stopped, libc::SIGINT),
+        _ = terminate.recv() => (Ended::Stopped, libc::SIGTERM),
+    };
+
+    // The child may have exited since, which only makes this fail
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, forwarded);
+    }
+    let _ = child.await;
+    ended
+}
+
+/// Wait before reconnecting, returning whether vssh was interrupted or terminated meanwhile
+async fn stopped_during(delay: u64, interrupt: &mut Signal, terminate: &mut Signal) -> bool {
+    tokio::select! {
+        _ = delay_for(Duration::from_secs(delay)) => false,
+        _ = interrupt.recv() => true,
+        _ = terminate.recv() => true,
+    }
+}
@@ -1,4 +1,10 @@
+use std::fmt::Write;
+use std::fs::DirBuilder;
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
 use std::process::exit;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub const VERSION: &str = "0.3.2";
 
@@ -7,3 +13,100 @@ pub fn fail(prompt: &'_ str) -> ! {
     leg::error(prompt, None, None);
     exit(1)
 }
+
+/// Get the path of a file in the cache directory, creating the directory if necessary.
+/// The cache is stored at `$HOME/.cache/vssh` and is only accessible by the current user.
+pub fn cache_file(name: &str) -> io::Result<PathBuf> {
+    let mut path = dirs::home_dir().expect("Failed to retrieve user's home directory");
+    path.push(".cache/vssh");
+
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&path)?;
+
+    path.push(name);
+    Ok(path)
+}
+
+/// Format a number of seconds as a human readable duration, i.e. `1h 5m 30s`
+pub fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Get the current UNIX timestamp in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the UNIX epoch")
+        .as_secs()
+}
+
+/// Format a UNIX timestamp as an RFC 3339 date in UTC, where the maximum value means forever.
+/// Timestamps past the year 9999 cannot be formatted as a date and are shown as is.
+pub fn format_timestamp(timestamp: u64) -> String {
+    if timestamp == u64::MAX {
+        return String::from("forever");
+    }
+
+    let mut formatted = String::new();
+    match UNIX_EPOCH.checked_add(Duration::from_secs(timestamp)) {
+        Some(time) if write!(formatted, "{}", humantime::format_rfc3339_seconds(time)).is_ok() => {
+            formatted
+        }
+        _ => timestamp.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh::Certificate;
+
+    /// Signed with `ssh-keygen -s ca -I t -n alice -V 0x1:0x7fffffffffffffff u.pub`
+    const FAR_FUTURE_CERTIFICATE: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAIJF4SMnkwryWBADLZHmufg3U/BYxdQLVzID3fKYfioKsAAAAIFlEUzY5RFNciftvxoAAssk0DkHm9HJnlxK5OaNsLVJqAAAAAAAAAAAAAAABAAAAAXQAAAAJAAAABWFsaWNlAAAAAAAAAAF//////////wAAAAAAAACCAAAAFXBlcm1pdC1YMTEtZm9yd2FyZGluZwAAAAAAAAAXcGVybWl0LWFnZW50LWZvcndhcmRpbmcAAAAAAAAAFnBlcm1pdC1wb3J0LWZvcndhcmRpbmcAAAAAAAAACnBlcm1pdC1wdHkAAAAAAAAADnBlcm1pdC11c2VyLXJjAAAAAAAAAAAAAAAzAAAAC3NzaC1lZDI1NTE5AAAAIIDJ3JGvHJtcqY8jd8PSpBUd3PlgqLR82+boCysrU0kaAAAAUwAAAAtzc2gtZWQyNTUxOQAAAEAZpAUQ+69w7x+xKM8PWzs+YGYVgxgUWkUrJZnp1T2LESBls+xYjIzq6qe0KXIEQPF5DoYQG0o3Px1YHIWeB58D u";
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_timestamp(253_402_300_799), "9999-12-31T23:59:59Z");
+        assert_eq!(format_timestamp(u64::MAX), "forever");
+    }
+
+    #[test]
+    fn shows_timestamps_past_year_9999_as_is() {
+        assert_eq!(format_timestamp(253_402_300_800), "253402300800");
+        assert_eq!(format_timestamp(i64::MAX as u64), "9223372036854775807");
+        assert_eq!(format_timestamp(u64::MAX - 1), "18446744073709551614");
+    }
+
+    #[test]
+    fn formats_far_future_certificate() {
+        let certificate = Certificate::parse(FAR_FUTURE_CERTIFICATE).unwrap();
+        assert_eq!(
+            format_timestamp(certificate.valid_after),
+            "1970-01-01T00:00:01Z"
+        );
+        assert_eq!(
+            format_timestamp(certificate.valid_before),
+            "9223372036854775807"
+        );
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(61), "1m 1s");
+        assert_eq!(format_duration(3_600), "1h 0m 0s");
+        assert_eq!(format_duration(3_930), "1h 5m 30s");
+    }
+}